@@ -1,60 +1,710 @@
 #![allow(non_snake_case)]
 
-const WIDTH: usize = 40;
+use cpu::{Cpu, Observer, Tick};
 
 fn main() {
     let input = include_str!("input.txt");
-    let (signal_strength_sum, crt_output) = process_input(input, vec![20, 60, 100, 140, 180, 220]);
+    let args = std::env::args().collect::<Vec<_>>();
+    let geometry = Geometry::from_args(&args);
+
+    let (signal_strength_sum, crt) =
+        process_input(input, vec![20, 60, 100, 140, 180, 220], geometry);
+    let crt_output = crt.text();
     println!("Signal Strength Sum: {signal_strength_sum}");
     println!();
     println!("Output:");
-    println!("{}", crt_output);
+
+    // `--blocks` draws the screen with block characters instead of `#` and `.`.
+    if args.iter().any(|a| a == "--blocks") {
+        println!("{}", crt.blocks());
+    } else {
+        println!("{}", crt_output);
+    }
+
+    // `--large-font` reads the screen as 6x10 letters rather than 4x6.
+    let font = if args.iter().any(|a| a == "--large-font") {
+        &ocr::LARGE
+    } else {
+        &ocr::SMALL
+    };
+
+    match ocr::read(&crt_output, font) {
+        Ok(letters) => println!("Letters: {letters}"),
+        Err(unknown) => {
+            for glyph in unknown {
+                println!("{glyph}");
+            }
+        }
+    }
+
+    if !args.iter().any(|a| a == "--noimage") {
+        if let Some(mut path) = dirs::desktop_dir() {
+            path.push("Day 10 CRT.png");
+            graphic::screen(&crt)
+                .save(path.clone())
+                .expect("Failed to save image.");
+            println!("Saved CRT image to {}", path.to_str().unwrap());
+        }
+    }
+
+    // `--trace` prints the state of the CPU during every cycle.
+    if args.iter().any(|a| a == "--trace") {
+        let mut trace = Trace::default();
+        let mut cpu = Cpu::new(cpu::parse(input));
+        cpu.run(&mut [&mut trace]);
+
+        println!();
+        print!("{}", trace.output);
+        println!("Halted: X={}", cpu.registers().get(cpu::Register::X));
+    }
 }
 
-fn process_input(input: &str, signal_strength_checkpoints: Vec<usize>) -> (isize, String) {
-    let mut cycle: usize = 1;
-    let mut x_register: isize = 1;
-    let mut signal_strength_sum = 0;
-    let mut crt_output = String::new();
+mod cpu {
+    use std::str::{FromStr, SplitWhitespace};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(crate) enum Register {
+        X,
+    }
+
+    impl Register {
+        /// Every register and the value it starts at, so a new register only
+        /// needs its variant and an entry here.
+        const ALL: &'static [(Register, isize)] = &[(Register::X, 1)];
+    }
+
+    /// Every register's value, indexed by `Register`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(crate) struct Registers([isize; Register::ALL.len()]);
+
+    impl Default for Registers {
+        fn default() -> Self {
+            let mut registers = Self([0; Register::ALL.len()]);
+
+            for (register, value) in Register::ALL {
+                registers.set(*register, *value);
+            }
 
-    let mut update = |cycle: usize, x_register: isize| {
-        if signal_strength_checkpoints.contains(&cycle) {
-            signal_strength_sum += cycle as isize * x_register;
+            registers
         }
+    }
 
-        let current_line_index = ((cycle - 1) % WIDTH) as isize;
-        let energized_indexes = (x_register - 1)..=(x_register + 1);
+    impl Registers {
+        pub(crate) fn get(&self, register: Register) -> isize {
+            self.0[register as usize]
+        }
 
-        if energized_indexes.contains(&current_line_index) {
-            crt_output.push('#');
-        } else {
-            crt_output.push('.');
+        fn set(&mut self, register: Register, value: isize) {
+            self.0[register as usize] = value;
         }
+    }
+
+    /// Declares `Instruction` from one match-like arm per instruction, giving
+    /// its variant and arguments, mnemonic, cycle count and effect:
+    ///
+    /// `Variant(argument: Type, ...) => "mnemonic", cycles, |registers| effect`
+    ///
+    /// Parsing, printing, `cycles` and `execute` are all generated from the
+    /// arms, so adding an instruction is adding an arm.
+    macro_rules! instruction_set {
+        ($(
+            $variant:ident $(($($argument:ident: $kind:ty),*))?
+                => $mnemonic:literal, $cycles:literal, |$registers:pat_param| $effect:expr
+        ),* $(,)?) => {
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            pub(crate) enum Instruction {
+                $($variant $(($($kind),*))?,)*
+            }
+
+            impl Instruction {
+                /// How many cycles the instruction takes to complete.
+                pub(crate) fn cycles(&self) -> usize {
+                    match self {
+                        $(Instruction::$variant { .. } => $cycles,)*
+                    }
+                }
+
+                /// Applies the instruction, which happens at the end of its
+                /// last cycle.
+                fn execute(&self, registers: &mut Registers) {
+                    match *self {
+                        $(Instruction::$variant $(($($argument),*))? => {
+                            let $registers = registers;
+                            $effect
+                        })*
+                    }
+                }
+            }
+
+            impl FromStr for Instruction {
+                type Err = String;
+
+                fn from_str(s: &str) -> Result<Self, Self::Err> {
+                    let mut parts = s.split_whitespace();
+
+                    let instruction = match parts.next() {
+                        $(Some($mnemonic) => Instruction::$variant $((
+                            $(argument::<$kind>(&mut parts, s)?),*
+                        ))?,)*
+                        _ => return Err(format!("Unknown instruction '{s}'.")),
+                    };
+
+                    match parts.next() {
+                        Some(_) => Err(format!("Too many arguments in '{s}'.")),
+                        None => Ok(instruction),
+                    }
+                }
+            }
+
+            impl std::fmt::Display for Instruction {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    match self {
+                        $(Instruction::$variant $(($($argument),*))? => {
+                            write!(f, "{}", $mnemonic)?;
+                            $($(write!(f, " {}", $argument)?;)*)?
+                            Ok(())
+                        })*
+                    }
+                }
+            }
+        };
+    }
+
+    fn argument<T: FromStr>(parts: &mut SplitWhitespace, s: &str) -> Result<T, String> {
+        parts
+            .next()
+            .and_then(|v| v.parse().ok())
+            .ok_or(format!("Unable to parse the argument in '{s}'."))
+    }
+
+    instruction_set! {
+        Noop => "noop", 1, |_| (),
+        Addx(value: isize) => "addx", 2, |registers| {
+            registers.set(Register::X, registers.get(Register::X) + value)
+        },
+    }
+
+    pub(crate) fn parse(input: &str) -> Vec<Instruction> {
+        input
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(|l| l.parse().unwrap_or_else(|error| panic!("{error}")))
+            .collect()
+    }
+
+    /// What the CPU looks like during a cycle, before the instruction being
+    /// worked on (if it's on its last cycle) takes effect.
+    #[derive(Debug, Clone, Copy)]
+    pub(crate) struct Tick {
+        /// Starts at one, like the puzzle.
+        pub(crate) cycle: usize,
+        pub(crate) registers: Registers,
+        pub(crate) instruction: Instruction,
+        /// Which of the instruction's cycles this is, starting at one.
+        pub(crate) progress: usize,
+    }
 
-        if current_line_index == (WIDTH - 1) as isize {
-            crt_output.push('\n');
+    /// Anything that wants to watch the CPU run.
+    pub(crate) trait Observer {
+        fn observe(&mut self, tick: &Tick);
+    }
+
+    pub(crate) struct Cpu {
+        program: Vec<Instruction>,
+        counter: usize,
+        registers: Registers,
+        cycle: usize,
+        /// How many cycles the current instruction has already taken.
+        progress: usize,
+    }
+
+    impl Cpu {
+        pub(crate) fn new(program: Vec<Instruction>) -> Self {
+            Self {
+                program,
+                counter: 0,
+                registers: Registers::default(),
+                cycle: 0,
+                progress: 0,
+            }
+        }
+
+        pub(crate) fn registers(&self) -> Registers {
+            self.registers
+        }
+
+        /// Runs a single cycle, or returns `None` once the program is done.
+        pub(crate) fn step(&mut self) -> Option<Tick> {
+            let instruction = *self.program.get(self.counter)?;
+            self.cycle += 1;
+            self.progress += 1;
+
+            let tick = Tick {
+                cycle: self.cycle,
+                registers: self.registers,
+                instruction,
+                progress: self.progress,
+            };
+
+            if self.progress == instruction.cycles() {
+                instruction.execute(&mut self.registers);
+                self.counter += 1;
+                self.progress = 0;
+            }
+
+            Some(tick)
         }
+
+        /// Runs the program to the end, showing every cycle to every observer.
+        pub(crate) fn run(&mut self, observers: &mut [&mut dyn Observer]) {
+            while let Some(tick) = self.step() {
+                for observer in observers.iter_mut() {
+                    observer.observe(&tick);
+                }
+            }
+        }
+    }
+}
+
+mod ocr {
+    /// A fixed width font of `#` and `.` glyphs, one row per line.
+    pub(crate) struct Font {
+        pub(crate) width: usize,
+        pub(crate) height: usize,
+        /// Distance from the start of one letter to the next.
+        pub(crate) pitch: usize,
+        pub(crate) glyphs: &'static [(char, &'static str)],
+    }
+
+    /// The 4x6 letters most days draw with.
+    pub(crate) const SMALL: Font = Font {
+        width: 4,
+        height: 6,
+        pitch: 5,
+        glyphs: &[
+            ('A', ".##.\n#..#\n#..#\n####\n#..#\n#..#"),
+            ('B', "###.\n#..#\n###.\n#..#\n#..#\n###."),
+            ('C', ".##.\n#..#\n#...\n#...\n#..#\n.##."),
+            ('E', "####\n#...\n###.\n#...\n#...\n####"),
+            ('F', "####\n#...\n###.\n#...\n#...\n#..."),
+            ('G', ".##.\n#..#\n#...\n#.##\n#..#\n.###"),
+            ('H', "#..#\n#..#\n####\n#..#\n#..#\n#..#"),
+            ('I', ".###\n..#.\n..#.\n..#.\n..#.\n.###"),
+            ('J', "..##\n...#\n...#\n...#\n#..#\n.##."),
+            ('K', "#..#\n#.#.\n##..\n#.#.\n#.#.\n#..#"),
+            ('L', "#...\n#...\n#...\n#...\n#...\n####"),
+            ('O', ".##.\n#..#\n#..#\n#..#\n#..#\n.##."),
+            ('P', "###.\n#..#\n#..#\n###.\n#...\n#..."),
+            ('R', "###.\n#..#\n#..#\n###.\n#.#.\n#..#"),
+            ('S', ".###\n#...\n#...\n.##.\n...#\n###."),
+            ('U', "#..#\n#..#\n#..#\n#..#\n#..#\n.##."),
+            ('Y', "#...\n#...\n.#.#\n..#.\n..#.\n..#."),
+            ('Z', "####\n...#\n..#.\n.#..\n#...\n####"),
+        ],
     };
 
-    for line in input.lines().filter(|l| !l.is_empty()) {
-        update(cycle, x_register);
+    /// The 6x10 letters a few older days draw with.
+    pub(crate) const LARGE: Font = Font {
+        width: 6,
+        height: 10,
+        pitch: 8,
+        glyphs: &[
+            (
+                'A',
+                "..##..\n.#..#.\n#....#\n#....#\n#....#\n######\n#....#\n#....#\n#....#\n#....#",
+            ),
+            (
+                'B',
+                "#####.\n#....#\n#....#\n#....#\n#####.\n#....#\n#....#\n#....#\n#....#\n#####.",
+            ),
+            (
+                'C',
+                ".####.\n#....#\n#.....\n#.....\n#.....\n#.....\n#.....\n#.....\n#....#\n.####.",
+            ),
+            (
+                'E',
+                "######\n#.....\n#.....\n#.....\n#####.\n#.....\n#.....\n#.....\n#.....\n######",
+            ),
+            (
+                'F',
+                "######\n#.....\n#.....\n#.....\n#####.\n#.....\n#.....\n#.....\n#.....\n#.....",
+            ),
+            (
+                'G',
+                ".####.\n#....#\n#.....\n#.....\n#.....\n#..###\n#....#\n#....#\n#...##\n.###.#",
+            ),
+            (
+                'H',
+                "#....#\n#....#\n#....#\n#....#\n######\n#....#\n#....#\n#....#\n#....#\n#....#",
+            ),
+            (
+                'J',
+                "...###\n....#.\n....#.\n....#.\n....#.\n....#.\n....#.\n#...#.\n#...#.\n.###..",
+            ),
+            (
+                'K',
+                "#....#\n#...#.\n#..#..\n#.#...\n##....\n##....\n#.#...\n#..#..\n#...#.\n#....#",
+            ),
+            (
+                'L',
+                "#.....\n#.....\n#.....\n#.....\n#.....\n#.....\n#.....\n#.....\n#.....\n######",
+            ),
+            (
+                'N',
+                "#....#\n##...#\n##...#\n#.#..#\n#.#..#\n#..#.#\n#..#.#\n#...##\n#...##\n#....#",
+            ),
+            (
+                'P',
+                "#####.\n#....#\n#....#\n#....#\n#####.\n#.....\n#.....\n#.....\n#.....\n#.....",
+            ),
+            (
+                'R',
+                "#####.\n#....#\n#....#\n#....#\n#####.\n#..#..\n#...#.\n#...#.\n#....#\n#....#",
+            ),
+            (
+                'X',
+                "#....#\n#....#\n.#..#.\n.#..#.\n..##..\n..##..\n.#..#.\n.#..#.\n#....#\n#....#",
+            ),
+            (
+                'Z',
+                "######\n.....#\n.....#\n....#.\n...#..\n..#...\n.#....\n#.....\n#.....\n######",
+            ),
+        ],
+    };
+
+    /// A glyph the font doesn't have, where it was found and what it looked like.
+    #[derive(Debug, PartialEq, Eq)]
+    pub(crate) struct UnknownGlyph {
+        /// Which line of text, and which letter along it, both from zero.
+        pub(crate) line: usize,
+        pub(crate) letter: usize,
+        /// The screen column the glyph starts at.
+        pub(crate) column: usize,
+        pub(crate) pattern: String,
+    }
+
+    impl std::fmt::Display for UnknownGlyph {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            writeln!(
+                f,
+                "Unknown glyph for letter {} of line {} (column {}):",
+                self.letter, self.line, self.column
+            )?;
+            write!(f, "{}", self.pattern)
+        }
+    }
+
+    /// Reads the letters on the screen. Every `font.height` rows make a line of
+    /// text, and blank glyphs become spaces. Every glyph the font doesn't know
+    /// is reported, not just the first.
+    pub(crate) fn read(screen: &str, font: &Font) -> Result<String, Vec<UnknownGlyph>> {
+        let rows = screen.lines().map(str::as_bytes).collect::<Vec<_>>();
+        let mut text = String::new();
+        let mut unknown = vec![];
+
+        for (line, rows) in rows.chunks(font.height).enumerate() {
+            if line > 0 {
+                text.push('\n');
+            }
+
+            let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+            let mut letters = String::new();
+
+            for (letter, column) in (0..width).step_by(font.pitch).enumerate() {
+                // Missing pixels, past the end of a row or below the last
+                // one, count as unlit.
+                let pattern = (0..font.height)
+                    .map(|y| {
+                        (column..column + font.width)
+                            .map(|x| match rows.get(y).and_then(|r| r.get(x)) {
+                                Some(b'#') => '#',
+                                _ => '.',
+                            })
+                            .collect::<String>()
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                if !pattern.contains('#') {
+                    letters.push(' ');
+                } else if let Some((c, _)) = font.glyphs.iter().find(|(_, g)| *g == pattern) {
+                    letters.push(*c);
+                } else {
+                    letters.push('?');
+                    unknown.push(UnknownGlyph {
+                        line,
+                        letter,
+                        column,
+                        pattern,
+                    });
+                }
+            }
+
+            text.push_str(letters.trim_end());
+        }
+
+        if unknown.is_empty() {
+            Ok(text)
+        } else {
+            Err(unknown)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{read, Font, UnknownGlyph, LARGE, SMALL};
+
+        /// Draws the given glyphs side by side, like the CRT would.
+        fn draw(font: &Font, letters: &[&str]) -> String {
+            (0..font.height)
+                .map(|y| {
+                    letters
+                        .iter()
+                        .map(|g| {
+                            let row = g.lines().nth(y).unwrap();
+                            format!("{row:.<width$}", width = font.pitch)
+                        })
+                        .collect::<String>()
+                        + "\n"
+                })
+                .collect()
+        }
+
+        #[test]
+        fn every_glyph_reads_back() {
+            for font in [SMALL, LARGE] {
+                let glyphs = font.glyphs.iter().map(|(_, g)| *g).collect::<Vec<_>>();
+                let expected = font.glyphs.iter().map(|(c, _)| *c).collect::<String>();
+                assert_eq!(read(&draw(&font, &glyphs), &font), Ok(expected));
+            }
+        }
+
+        #[test]
+        fn unknown_glyphs_are_located() {
+            let smiley = "....\n#..#\n....\n#..#\n.##.\n....";
+            let screen = draw(&SMALL, &[SMALL.glyphs[0].1, smiley, SMALL.glyphs[1].1]);
 
-        let trimmed = line.trim();
-        if trimmed == "noop" {
-            cycle += 1;
-            continue;
+            assert_eq!(
+                read(&screen, &SMALL),
+                Err(vec![UnknownGlyph {
+                    line: 0,
+                    letter: 1,
+                    column: 5,
+                    pattern: smiley.to_string(),
+                }])
+            );
         }
+    }
+}
+
+/// Sums the signal strength during the given cycles.
+struct SignalStrength {
+    checkpoints: Vec<usize>,
+    sum: isize,
+}
+
+impl Observer for SignalStrength {
+    fn observe(&mut self, tick: &Tick) {
+        if self.checkpoints.contains(&tick.cycle) {
+            self.sum += tick.cycle as isize * tick.registers.get(cpu::Register::X);
+        }
+    }
+}
+
+/// The size of the screen and of the sprite drawn on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Geometry {
+    width: usize,
+    height: usize,
+    sprite_width: usize,
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Self {
+            width: 40,
+            height: 6,
+            sprite_width: 3,
+        }
+    }
+}
+
+impl Geometry {
+    /// The default geometry, with any of `--width`, `--height` and `--sprite`
+    /// overriding it.
+    fn from_args(args: &[String]) -> Self {
+        let value = |flag: &str| {
+            let index = args.iter().position(|a| a == flag)?;
+            let value = args.get(index + 1).and_then(|a| a.parse().ok());
+            Some(value.unwrap_or_else(|| panic!("Expected a positive number after {flag}.")))
+        };
+
+        let default = Geometry::default();
+        let geometry = Geometry {
+            width: value("--width").unwrap_or(default.width),
+            height: value("--height").unwrap_or(default.height),
+            sprite_width: value("--sprite").unwrap_or(default.sprite_width),
+        };
+
+        assert!(
+            geometry.width > 0 && geometry.height > 0,
+            "The screen can't be empty."
+        );
+        geometry
+    }
+}
+
+/// Draws a pixel every cycle, lit wherever the sprite overlaps it. Once the
+/// last row is done it starts over from the top, like a real beam would.
+struct Crt {
+    geometry: Geometry,
+    pixels: Vec<bool>,
+}
 
-        cycle += 1;
-        update(cycle, x_register);
-        cycle += 1;
+impl Crt {
+    fn new(geometry: Geometry) -> Self {
+        Self {
+            geometry,
+            pixels: vec![false; geometry.width * geometry.height],
+        }
+    }
+
+    fn rows(&self) -> impl Iterator<Item = &[bool]> {
+        self.pixels.chunks(self.geometry.width)
+    }
 
-        let value_string = trimmed.strip_prefix("addx ").expect("Unexpected input.");
-        let parsed: isize = value_string.parse().expect("Unable to parse 'addx' value.");
-        x_register += parsed;
+    /// The screen as `#` and `.`, a line per row.
+    fn text(&self) -> String {
+        self.rows()
+            .map(|row| {
+                row.iter()
+                    .map(|&lit| if lit { '#' } else { '.' })
+                    .chain(['\n'])
+                    .collect::<String>()
+            })
+            .collect()
     }
 
-    (signal_strength_sum, crt_output)
+    /// The screen in block characters, two rows to a line so the pixels come
+    /// out roughly square.
+    fn blocks(&self) -> String {
+        let rows = self.rows().collect::<Vec<_>>();
+
+        rows.chunks(2)
+            .map(|pair| {
+                (0..self.geometry.width)
+                    .map(|x| {
+                        let top = pair[0][x];
+                        let bottom = pair.get(1).is_some_and(|row| row[x]);
+
+                        match (top, bottom) {
+                            (true, true) => '█',
+                            (true, false) => '▀',
+                            (false, true) => '▄',
+                            (false, false) => ' ',
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl Observer for Crt {
+    fn observe(&mut self, tick: &Tick) {
+        let Geometry {
+            width,
+            sprite_width,
+            ..
+        } = self.geometry;
+
+        let index = (tick.cycle - 1) % self.pixels.len();
+        let current_line_index = (index % width) as isize;
+
+        // The sprite is centred on X, leaning right when its width is even.
+        let x_register = tick.registers.get(cpu::Register::X);
+        let left = x_register - (sprite_width as isize - 1) / 2;
+        let energized_indexes = left..left + sprite_width as isize;
+
+        self.pixels[index] = energized_indexes.contains(&current_line_index);
+    }
+}
+
+mod graphic {
+    use crate::Crt;
+    use image::{Rgb, RgbImage};
+
+    const SCALE: u32 = 12;
+
+    const BACKGROUND: Rgb<u8> = Rgb([12, 16, 14]);
+    const PHOSPHOR: Rgb<u8> = Rgb([110, 255, 140]);
+    const GLOW: Rgb<u8> = Rgb([40, 90, 55]);
+
+    fn dim(color: Rgb<u8>, factor: f32) -> Rgb<u8> {
+        Rgb(color.0.map(|c| (c as f32 * factor) as u8))
+    }
+
+    /// The screen scaled up, with lit pixels glowing slightly at their edges
+    /// and every third line darkened like a scanline.
+    pub(crate) fn screen(crt: &Crt) -> RgbImage {
+        let width = crt.geometry.width as u32;
+        let height = crt.geometry.height as u32;
+        let mut image = RgbImage::new(width * SCALE, height * SCALE);
+
+        for (px, py, pixel) in image.enumerate_pixels_mut() {
+            let (x, y) = ((px / SCALE) as usize, (py / SCALE) as usize);
+            let (inner_x, inner_y) = (px % SCALE, py % SCALE);
+            let edge = inner_x == 0 || inner_y == 0 || inner_x == SCALE - 1 || inner_y == SCALE - 1;
+
+            let color = match (crt.pixels[y * crt.geometry.width + x], edge) {
+                (true, false) => PHOSPHOR,
+                (true, true) => GLOW,
+                (false, _) => BACKGROUND,
+            };
+
+            *pixel = if py % 3 == 2 { dim(color, 0.45) } else { color };
+        }
+
+        image
+    }
+}
+
+/// A line per cycle, e.g. `Cycle 3: X=1, addx -1 (1/2)`.
+#[derive(Default)]
+struct Trace {
+    output: String,
+}
+
+impl Observer for Trace {
+    fn observe(&mut self, tick: &Tick) {
+        let line = format!(
+            "Cycle {}: X={}, {} ({}/{})\n",
+            tick.cycle,
+            tick.registers.get(cpu::Register::X),
+            tick.instruction,
+            tick.progress,
+            tick.instruction.cycles()
+        );
+        self.output.push_str(&line);
+    }
+}
+
+fn process_input(
+    input: &str,
+    signal_strength_checkpoints: Vec<usize>,
+    geometry: Geometry,
+) -> (isize, Crt) {
+    let mut signal = SignalStrength {
+        checkpoints: signal_strength_checkpoints,
+        sum: 0,
+    };
+    let mut crt = Crt::new(geometry);
+
+    Cpu::new(cpu::parse(input)).run(&mut [&mut signal, &mut crt]);
+
+    (signal.sum, crt)
 }
 
 #[cfg(test)]
@@ -64,7 +714,12 @@ mod tests {
     #[test]
     fn test_1() {
         let example = "addx 15\naddx -11\naddx 6\naddx -3\naddx 5\naddx -1\naddx -8\naddx 13\naddx 4\nnoop\naddx -1\naddx 5\naddx -1\naddx 5\naddx -1\naddx 5\naddx -1\naddx 5\naddx -1\naddx -35\naddx 1\naddx 24\naddx -19\naddx 1\naddx 16\naddx -11\nnoop\nnoop\naddx 21\naddx -15\nnoop\nnoop\naddx -3\naddx 9\naddx 1\naddx -3\naddx 8\naddx 1\naddx 5\nnoop\nnoop\nnoop\nnoop\nnoop\naddx -36\nnoop\naddx 1\naddx 7\nnoop\nnoop\nnoop\naddx 2\naddx 6\nnoop\nnoop\nnoop\nnoop\nnoop\naddx 1\nnoop\nnoop\naddx 7\naddx 1\nnoop\naddx -13\naddx 13\naddx 7\nnoop\naddx 1\naddx -33\nnoop\nnoop\nnoop\naddx 2\nnoop\nnoop\nnoop\naddx 8\nnoop\naddx -1\naddx 2\naddx 1\nnoop\naddx 17\naddx -9\naddx 1\naddx 1\naddx -3\naddx 11\nnoop\nnoop\naddx 1\nnoop\naddx 1\nnoop\nnoop\naddx -13\naddx -19\naddx 1\naddx 3\naddx 26\naddx -30\naddx 12\naddx -1\naddx 3\naddx 1\nnoop\nnoop\nnoop\naddx -9\naddx 18\naddx 1\naddx 2\nnoop\nnoop\naddx 9\nnoop\nnoop\nnoop\naddx -1\naddx 2\naddx -37\naddx 1\naddx 3\nnoop\naddx 15\naddx -21\naddx 22\naddx -6\naddx 1\nnoop\naddx 2\naddx 1\nnoop\naddx -10\nnoop\nnoop\naddx 20\naddx 1\naddx 2\naddx 2\naddx -6\naddx -11\nnoop\nnoop\nnoop";
-        let result = process_input(example, vec![20, 60, 100, 140, 180, 220]).0;
+        let result = process_input(
+            example,
+            vec![20, 60, 100, 140, 180, 220],
+            Geometry::default(),
+        )
+        .0;
         let expected = 13140;
         assert_eq!(result, expected);
     }
@@ -72,8 +727,94 @@ mod tests {
     #[test]
     fn test_2() {
         let example = "noop\naddx 2\naddx -3\nnoop\naddx 1";
-        let result = process_input(example, vec![4, 6]).0;
+        let result = process_input(example, vec![4, 6], Geometry::default()).0;
         let expected = 12;
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn reads_the_real_output() {
+        let (_, crt) = process_input(include_str!("input.txt"), vec![], Geometry::default());
+        assert_eq!(
+            ocr::read(&crt.text(), &ocr::SMALL),
+            Ok("ECZUZALR".to_string())
+        );
+    }
+
+    #[test]
+    fn geometry() {
+        let program = "addx 3\nnoop\nnoop\nnoop\nnoop\nnoop\nnoop";
+        let narrow = Geometry {
+            width: 4,
+            height: 2,
+            sprite_width: 1,
+        };
+
+        // Only the column under X lights up.
+        let (_, crt) = process_input(program, vec![], narrow);
+        assert_eq!(crt.text(), ".#..\n....\n");
+
+        // An even width sprite leans right.
+        let wide = Geometry {
+            sprite_width: 4,
+            ..narrow
+        };
+
+        let (_, crt) = process_input(program, vec![], wide);
+        assert_eq!(crt.text(), "##.#\n...#\n");
+        assert_eq!(crt.blocks(), "▀▀ █");
+
+        // A second frame draws over the first.
+        let short = Geometry {
+            height: 1,
+            ..narrow
+        };
+
+        let (_, crt) = process_input(program, vec![], short);
+        assert_eq!(crt.text(), "....\n");
+    }
+
+    #[test]
+    fn stepping() {
+        let mut cpu = Cpu::new(cpu::parse("noop\naddx 3\naddx -5"));
+        let mut x = vec![];
+
+        while let Some(tick) = cpu.step() {
+            x.push(tick.registers.get(cpu::Register::X));
+        }
+
+        // The example from the puzzle: X only changes after each addx is done.
+        assert_eq!(x, vec![1, 1, 1, 4, 4]);
+        assert_eq!(cpu.registers().get(cpu::Register::X), -1);
+        assert!(cpu.step().is_none());
+    }
+
+    #[test]
+    fn tracing() {
+        let mut trace = Trace::default();
+        Cpu::new(cpu::parse("noop\naddx 3")).run(&mut [&mut trace]);
+
+        assert_eq!(
+            trace.output,
+            "Cycle 1: X=1, noop (1/1)\nCycle 2: X=1, addx 3 (1/2)\nCycle 3: X=1, addx 3 (2/2)\n"
+        );
+        assert!("addx".parse::<cpu::Instruction>().is_err());
+        assert!("addx 1 2".parse::<cpu::Instruction>().is_err());
+        assert!("noop 1".parse::<cpu::Instruction>().is_err());
+        assert!("jump 3".parse::<cpu::Instruction>().is_err());
+    }
+
+    #[test]
+    fn instruction_set() {
+        use cpu::Instruction;
+
+        for (text, instruction, cycles) in [
+            ("noop", Instruction::Noop, 1),
+            ("addx -12", Instruction::Addx(-12), 2),
+        ] {
+            assert_eq!(text.parse(), Ok(instruction));
+            assert_eq!(instruction.to_string(), text);
+            assert_eq!(instruction.cycles(), cycles);
+        }
+    }
 }
@@ -1,161 +1,1383 @@
 #![allow(non_snake_case)]
 
+use cycles::cyclic_inspections;
+use modular::Residue;
 use num_bigint::BigUint;
+use operation::{Expression, Worry};
+use params::{Params, Relief};
+use report::Log;
+use score::Score;
 
-const INPUT_ERR: &str = "The formatting of the input was unexpected.";
-const CLOSURE_USAGE_ERR: &str =
-    "This closure should never have been called, as it's just a placeholder.";
-const OPP_ERR: &str = "Unknown operation in the input.";
+const EVAL_ERR: &str = "An operation went below zero or divided by zero.";
+const INDEX_ERR: &str = "A monkey threw to a monkey that doesn't exist.";
 
 fn main() {
     let input = include_str!("input.txt");
-    let part_one = monkey_business(input, 3, 20);
+    let monkeys = || parse_monkeys(input).unwrap_or_else(|error| panic!("{error}"));
+
+    // `--bench [runs]` times the modular and big integer paths for part two.
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Some(index) = args.iter().position(|a| a == "--bench") {
+        let runs = args
+            .get(index + 1)
+            .and_then(|a| a.parse().ok())
+            .unwrap_or(10);
+        bench(input, runs);
+        return;
+    }
+
+    // `--score <top-k|sum|ranking>` changes how the counts are combined, and
+    // `--relief <expression>` replaces part one's `old / 3`.
+    let score = match args.iter().position(|a| a == "--score") {
+        Some(index) => args
+            .get(index + 1)
+            .expect("Expected a score after --score.")
+            .parse()
+            .unwrap_or_else(|error| panic!("{error}")),
+        None => Score::TopProduct(2),
+    };
+
+    let mut part_one_params = Params::part_one();
+    if let Some(index) = args.iter().position(|a| a == "--relief") {
+        let relief = args
+            .get(index + 1)
+            .and_then(|a| operation::parse(a))
+            .expect("Expected a relief expression of old after --relief.");
+        part_one_params = part_one_params.with_relief(Relief::Function(relief));
+    }
+
+    let part_one = score.apply(&inspections(monkeys(), &part_one_params));
     println!("Part One Business: {part_one}");
-    let part_two = monkey_business(input, 1, 10000);
+    let part_two = score.apply(&inspections(monkeys(), &Params::part_two()));
     println!("Part Two Business: {part_two}");
+
+    // `--rounds <n>` plays part two for any number of rounds, e.g. 10^12.
+    if let Some(index) = args.iter().position(|a| a == "--rounds") {
+        let rounds = args
+            .get(index + 1)
+            .and_then(|a| a.parse().ok())
+            .expect("Expected a round count after --rounds.");
+        let params = Params::part_two().with_rounds(rounds);
+        let business = score.apply(&inspections(monkeys(), &params));
+        println!("Business After {rounds} Rounds: {business}");
+    }
+
+    // `--report [rounds]` lists the inspection counts after each of the given
+    // comma separated rounds of part two, and `--part-one` switches to part
+    // one's relief and round count. `--throws` also logs every throw, and
+    // `--csv` saves both to the desktop rather than printing them. Unless an
+    // operation divides or subtracts, thrown worry levels are logged modulo
+    // the product of the test divisors.
+    if let Some(index) = args.iter().position(|a| a == "--report") {
+        let params = if args.iter().any(|a| a == "--part-one") {
+            part_one_params
+        } else {
+            Params::part_two()
+        };
+
+        let checkpoints = match args.get(index + 1).filter(|a| !a.starts_with("--")) {
+            Some(list) => list
+                .split(',')
+                .map(|r| r.parse().expect("Expected comma separated rounds."))
+                .collect(),
+            None => [1, 20]
+                .into_iter()
+                .chain((1000..=params.rounds).step_by(1000))
+                .filter(|&r| r <= params.rounds)
+                .collect(),
+        };
+
+        let mut log = Log::new(checkpoints, args.iter().any(|a| a == "--throws"));
+        logged_inspections(monkeys(), &params, &mut log);
+
+        if args.iter().any(|a| a == "--csv") {
+            let desktop = dirs::desktop_dir().expect("Couldn't find the desktop.");
+            let files = [
+                ("Day 11 Inspections.csv", Some(log.counts_csv())),
+                ("Day 11 Throws.csv", log.throws_csv()),
+            ];
+
+            for (name, csv) in files {
+                let Some(csv) = csv else { continue };
+                let mut path = desktop.clone();
+                path.push(name);
+                std::fs::write(&path, csv).expect("Failed to save CSV.");
+                println!("Saved {} to {}", name, path.to_str().unwrap());
+            }
+        } else {
+            print!("{}", log.to_text());
+
+            for throw in log.throws.iter().flatten() {
+                println!(
+                    "Round {}: monkey {} threw an item with worry residue {} (was {}) to monkey {}.",
+                    throw.round, throw.from, throw.residue, throw.item, throw.to
+                );
+            }
+        }
+    }
 }
 
-struct Monkey {
+struct Monkey<T = BigUint> {
     inspections: u64,
-    items: Vec<BigUint>,
-    operation: Box<dyn Fn(BigUint) -> BigUint>,
+    items: Vec<T>,
+    operation: Expression,
     test_divisor: u64,
     true_index: usize,
     false_index: usize,
 }
 
-impl Default for Monkey {
-    fn default() -> Self {
-        Monkey {
-            inspections: 0,
-            items: vec![],
-            operation: Box::new(|_| panic!("{CLOSURE_USAGE_ERR}")),
-            test_divisor: 0,
-            true_index: 0,
-            false_index: 0,
+mod operation {
+    use num_bigint::BigUint;
+    use num_traits::{CheckedDiv, CheckedSub, Zero};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(crate) enum Operator {
+        Add,
+        Subtract,
+        Multiply,
+        Divide,
+    }
+
+    /// The right hand side of an `Operation: new = ...` line.
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    pub(crate) enum Expression {
+        #[default]
+        Old,
+        Literal(BigUint),
+        Binary(Box<Expression>, Operator, Box<Expression>),
+    }
+
+    /// A number type an expression can be evaluated over. Each operation
+    /// returns `None` when the result isn't representable, e.g. going below
+    /// zero.
+    pub(crate) trait Worry: Sized + Clone + std::fmt::Display {
+        /// A literal converted into the same number space as `self`.
+        fn literal(&self, value: &BigUint) -> Option<Self>;
+        fn apply(&self, operator: Operator, other: &Self) -> Option<Self>;
+        fn divisible_by(&self, divisor: u64) -> bool;
+    }
+
+    impl Worry for BigUint {
+        fn literal(&self, value: &BigUint) -> Option<Self> {
+            Some(value.clone())
+        }
+
+        fn divisible_by(&self, divisor: u64) -> bool {
+            (self % divisor).is_zero()
+        }
+
+        fn apply(&self, operator: Operator, other: &Self) -> Option<Self> {
+            match operator {
+                Operator::Add => Some(self + other),
+                Operator::Subtract => self.checked_sub(other),
+                Operator::Multiply => Some(self * other),
+                Operator::Divide if other.is_zero() => None,
+                Operator::Divide => self.checked_div(other),
+            }
+        }
+    }
+
+    impl Expression {
+        pub(crate) fn evaluate<T: Worry>(&self, old: &T) -> Option<T> {
+            match self {
+                Expression::Old => Some(old.clone()),
+                Expression::Literal(value) => old.literal(value),
+                Expression::Binary(left, operator, right) => {
+                    let left = left.evaluate(old)?;
+                    let right = right.evaluate(old)?;
+                    left.apply(*operator, &right)
+                }
+            }
+        }
+    }
+
+    impl Expression {
+        /// Whether the expression only adds and multiplies, so it gives the
+        /// same remainders when worked modulo a multiple of the divisor.
+        /// Division doesn't survive the reduction, and subtraction could take
+        /// a reduced value below zero where the real one wouldn't be.
+        pub(crate) fn reducible(&self) -> bool {
+            match self {
+                Expression::Binary(left, operator, right) => {
+                    matches!(operator, Operator::Add | Operator::Multiply)
+                        && left.reducible()
+                        && right.reducible()
+                }
+                _ => true,
+            }
+        }
+
+        /// The same expression with `old` replaced by another expression.
+        pub(crate) fn substitute(&self, old: &Expression) -> Expression {
+            match self {
+                Expression::Old => old.clone(),
+                Expression::Literal(_) => self.clone(),
+                Expression::Binary(left, operator, right) => Expression::Binary(
+                    Box::new(left.substitute(old)),
+                    *operator,
+                    Box::new(right.substitute(old)),
+                ),
+            }
+        }
+
+        /// The same expression with every literal taken modulo `modulus`.
+        pub(crate) fn reduced(&self, modulus: u64) -> Expression {
+            match self {
+                Expression::Old => Expression::Old,
+                Expression::Literal(value) => Expression::Literal(value % modulus),
+                Expression::Binary(left, operator, right) => Expression::Binary(
+                    Box::new(left.reduced(modulus)),
+                    *operator,
+                    Box::new(right.reduced(modulus)),
+                ),
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Token {
+        Old,
+        Number(BigUint),
+        Operator(Operator),
+    }
+
+    fn tokenize(input: &str) -> Option<Vec<Token>> {
+        let mut tokens = vec![];
+        let mut rest = input.trim_start();
+
+        while let Some(c) = rest.chars().next() {
+            let length = match c {
+                '+' | '-' | '*' | '/' => {
+                    tokens.push(Token::Operator(match c {
+                        '+' => Operator::Add,
+                        '-' => Operator::Subtract,
+                        '*' => Operator::Multiply,
+                        _ => Operator::Divide,
+                    }));
+                    1
+                }
+                _ if rest.starts_with("old") => {
+                    tokens.push(Token::Old);
+                    3
+                }
+                _ if c.is_ascii_digit() => {
+                    let length = rest
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap_or(rest.len());
+                    tokens.push(Token::Number(rest[..length].parse().ok()?));
+                    length
+                }
+                _ => return None,
+            };
+
+            rest = rest[length..].trim_start();
+        }
+
+        Some(tokens)
+    }
+
+    /// Parses an expression like `old * old` or `3 - old / 2`. Multiplication
+    /// and division bind tighter than addition and subtraction, and operators
+    /// of the same precedence apply left to right.
+    pub(crate) fn parse(input: &str) -> Option<Expression> {
+        let tokens = tokenize(input)?;
+        let mut position = 0;
+        let expression = sum(&tokens, &mut position)?;
+
+        // Anything left over means the expression was malformed.
+        (position == tokens.len()).then_some(expression)
+    }
+
+    fn sum(tokens: &[Token], position: &mut usize) -> Option<Expression> {
+        let mut left = product(tokens, position)?;
+
+        while let Some(Token::Operator(operator @ (Operator::Add | Operator::Subtract))) =
+            tokens.get(*position)
+        {
+            *position += 1;
+            let right = product(tokens, position)?;
+            left = Expression::Binary(Box::new(left), *operator, Box::new(right));
+        }
+
+        Some(left)
+    }
+
+    fn product(tokens: &[Token], position: &mut usize) -> Option<Expression> {
+        let mut left = atom(tokens, position)?;
+
+        while let Some(Token::Operator(operator @ (Operator::Multiply | Operator::Divide))) =
+            tokens.get(*position)
+        {
+            *position += 1;
+            let right = atom(tokens, position)?;
+            left = Expression::Binary(Box::new(left), *operator, Box::new(right));
+        }
+
+        Some(left)
+    }
+
+    fn atom(tokens: &[Token], position: &mut usize) -> Option<Expression> {
+        let expression = match tokens.get(*position)? {
+            Token::Old => Expression::Old,
+            Token::Number(value) => Expression::Literal(value.clone()),
+            Token::Operator(_) => return None,
+        };
+
+        *position += 1;
+        Some(expression)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{parse, Expression, Operator};
+        use num_bigint::BigUint;
+
+        fn evaluate(input: &str, old: u32) -> Option<BigUint> {
+            parse(input).unwrap().evaluate(&BigUint::from(old))
+        }
+
+        #[test]
+        fn parsing() {
+            assert_eq!(
+                parse("old * 19"),
+                Some(Expression::Binary(
+                    Box::new(Expression::Old),
+                    Operator::Multiply,
+                    Box::new(Expression::Literal(19u8.into()))
+                ))
+            );
+            assert_eq!(parse("old"), Some(Expression::Old));
+            assert_eq!(parse("old +"), None);
+            assert_eq!(parse("old % 2"), None);
+            assert_eq!(parse("old old"), None);
+        }
+
+        #[test]
+        fn evaluation() {
+            assert_eq!(evaluate("old * old", 9), Some(81u8.into()));
+            assert_eq!(evaluate("7+old", 9), Some(16u8.into()));
+            assert_eq!(evaluate("100 - old / 3 * 2", 9), Some(94u8.into()));
+            assert_eq!(evaluate("old - 10", 9), None);
+            assert_eq!(evaluate("old / 0", 9), None);
+
+            let huge = "123456789012345678901234567890";
+            assert_eq!(
+                evaluate(&format!("old + {huge}"), 10).unwrap().to_string(),
+                "123456789012345678901234567900"
+            );
         }
     }
 }
 
-fn monkey_business(input: &str, worry_divider: u32, rounds: u32) -> u64 {
-    let mut monkeys: Vec<Monkey> = vec![Monkey::default()];
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ParseError {
+    /// A line that started like part of a monkey but didn't follow through.
+    Format {
+        line: usize,
+    },
+    Operation {
+        line: usize,
+    },
+    ZeroDivisor {
+        monkey: usize,
+    },
+    UnknownTarget {
+        monkey: usize,
+        target: usize,
+    },
+    /// A monkey without one of its lines, named by how the line starts.
+    Missing {
+        monkey: usize,
+        line: &'static str,
+    },
+}
 
-    for line in input.lines() {
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Format { line } => write!(f, "Unexpected formatting on line {line}."),
+            ParseError::Operation { line } => {
+                write!(f, "Couldn't parse the operation on line {line}.")
+            }
+            ParseError::ZeroDivisor { monkey } => {
+                write!(f, "Monkey {monkey} tests for divisibility by zero.")
+            }
+            ParseError::UnknownTarget { monkey, target } => write!(
+                f,
+                "Monkey {monkey} throws to monkey {target}, which doesn't exist."
+            ),
+            ParseError::Missing { monkey, line } => {
+                write!(f, "Monkey {monkey} has no \"{line}\" line.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// A monkey as it's being read, with each line's value once it's been seen.
+#[derive(Default)]
+struct MonkeyLines {
+    items: Option<Vec<BigUint>>,
+    operation: Option<Expression>,
+    test_divisor: Option<u64>,
+    true_index: Option<usize>,
+    false_index: Option<usize>,
+}
+
+impl MonkeyLines {
+    fn build(self, monkey: usize) -> Result<Monkey, ParseError> {
+        let missing = |line| ParseError::Missing { monkey, line };
+
+        Ok(Monkey {
+            inspections: 0,
+            items: self.items.ok_or(missing("Starting items"))?,
+            operation: self.operation.ok_or(missing("Operation"))?,
+            test_divisor: self.test_divisor.ok_or(missing("Test"))?,
+            true_index: self.true_index.ok_or(missing("If true"))?,
+            false_index: self.false_index.ok_or(missing("If false"))?,
+        })
+    }
+}
+
+// Each line can only be given once.
+fn fill<T>(slot: &mut Option<T>, value: T, error: ParseError) -> Result<(), ParseError> {
+    match slot.replace(value) {
+        Some(_) => Err(error),
+        None => Ok(()),
+    }
+}
+
+fn parse_monkeys(input: &str) -> Result<Vec<Monkey>, ParseError> {
+    let mut lines: Vec<MonkeyLines> = vec![];
+
+    for (number, line) in input.lines().enumerate() {
         let trimmed = line.trim();
+        let format_error = ParseError::Format { line: number + 1 };
 
+        // Blank lines only separate monkeys, so any number of them is fine.
         if trimmed.is_empty() {
-            monkeys.push(Monkey::default());
             continue;
         }
 
-        if trimmed.starts_with("Starting items") {
-            let list = trimmed.strip_prefix("Starting items: ").expect(INPUT_ERR);
-            let item_strings = list.split(", ");
-            let items: Vec<BigUint> = item_strings.map(|s| s.parse().expect(INPUT_ERR)).collect();
-            monkeys.last_mut().unwrap().items = items;
+        // A monkey starts at its header, numbered in order from zero since
+        // the throws refer to monkeys by their position.
+        if let Some(header) = trimmed.strip_prefix("Monkey ") {
+            header
+                .strip_suffix(':')
+                .and_then(|index| index.parse::<usize>().ok())
+                .filter(|index| *index == lines.len())
+                .ok_or(format_error)?;
+
+            lines.push(MonkeyLines::default());
+            continue;
+        }
+
+        let Some(monkey) = lines.last_mut() else {
+            return Err(format_error);
+        };
+
+        if let Some(list) = trimmed.strip_prefix("Starting items:") {
+            let items = list
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                    s.parse()
+                        .map_err(|_| ParseError::Format { line: number + 1 })
+                })
+                .collect::<Result<Vec<BigUint>, _>>()?;
+            fill(&mut monkey.items, items, format_error)?;
+        } else if let Some(operation_string) = trimmed.strip_prefix("Operation: new = ") {
+            let operation = operation::parse(operation_string)
+                .ok_or(ParseError::Operation { line: number + 1 })?;
+            fill(&mut monkey.operation, operation, format_error)?;
+        } else if let Some(divisor) = trimmed.strip_prefix("Test: divisible by ") {
+            let divisor = divisor.parse().map_err(|_| format_error.clone())?;
+            fill(&mut monkey.test_divisor, divisor, format_error)?;
+        } else if let Some(index) = trimmed.strip_prefix("If true: throw to monkey ") {
+            let index = index.parse().map_err(|_| format_error.clone())?;
+            fill(&mut monkey.true_index, index, format_error)?;
+        } else if let Some(index) = trimmed.strip_prefix("If false: throw to monkey ") {
+            let index = index.parse().map_err(|_| format_error.clone())?;
+            fill(&mut monkey.false_index, index, format_error)?;
+        } else {
+            return Err(format_error);
+        }
+    }
+
+    let monkeys = lines
+        .into_iter()
+        .enumerate()
+        .map(|(monkey, lines)| lines.build(monkey))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Catch these here rather than part way through a simulation.
+    for (monkey, m) in monkeys.iter().enumerate() {
+        if m.test_divisor == 0 {
+            return Err(ParseError::ZeroDivisor { monkey });
+        }
+
+        for target in [m.true_index, m.false_index] {
+            if target >= monkeys.len() {
+                return Err(ParseError::UnknownTarget { monkey, target });
+            }
+        }
+    }
+
+    Ok(monkeys)
+}
+
+mod params {
+    use crate::{
+        operation::{Expression, Operator},
+        Monkey,
+    };
+
+    /// What happens to an item's worry level after it's inspected.
+    pub(crate) enum Relief {
+        /// Divide by the given amount, rounding down. Dividing by one means
+        /// there's no relief at all.
+        Divide(u32),
+        /// Any expression of `old`, like `old / 2 + 1`.
+        Function(Expression),
+    }
+
+    impl Relief {
+        fn expression(&self) -> Option<Expression> {
+            match self {
+                Relief::Divide(1) => None,
+                Relief::Divide(divider) => Some(Expression::Binary(
+                    Box::new(Expression::Old),
+                    Operator::Divide,
+                    Box::new(Expression::Literal((*divider).into())),
+                )),
+                Relief::Function(expression) => Some(expression.clone()),
+            }
+        }
+
+        /// Folds the relief into every monkey's operation, so the solvers
+        /// only have operations to deal with.
+        pub(crate) fn apply(&self, mut monkeys: Vec<Monkey>) -> Vec<Monkey> {
+            if let Some(relief) = self.expression() {
+                for monkey in &mut monkeys {
+                    monkey.operation = relief.substitute(&monkey.operation);
+                }
+            }
+
+            monkeys
+        }
+    }
+
+    pub(crate) struct Params {
+        pub(crate) rounds: u64,
+        pub(crate) relief: Relief,
+    }
+
+    impl Params {
+        pub(crate) fn part_one() -> Self {
+            Self {
+                rounds: 20,
+                relief: Relief::Divide(3),
+            }
+        }
+
+        pub(crate) fn part_two() -> Self {
+            Self {
+                rounds: 10000,
+                relief: Relief::Divide(1),
+            }
+        }
+
+        pub(crate) fn with_rounds(self, rounds: u64) -> Self {
+            Self { rounds, ..self }
+        }
+
+        pub(crate) fn with_relief(self, relief: Relief) -> Self {
+            Self { relief, ..self }
+        }
+    }
+}
+
+mod score {
+    use std::{fmt::Display, str::FromStr};
+
+    /// How the inspection counts are turned into a final answer.
+    #[derive(Debug, PartialEq, Eq)]
+    pub(crate) enum Score {
+        /// The product of the k highest counts. The puzzle uses two.
+        TopProduct(usize),
+        Sum,
+        /// Every monkey, busiest first.
+        Ranking,
+    }
+
+    pub(crate) enum Scored {
+        Value(u128),
+        Ranking(Vec<(usize, u64)>),
+    }
+
+    impl FromStr for Score {
+        type Err = String;
+
+        /// Accepts `top-<k>`, `sum` or `ranking`.
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "sum" => Ok(Score::Sum),
+                "ranking" => Ok(Score::Ranking),
+                _ => s
+                    .strip_prefix("top-")
+                    .and_then(|k| k.parse().ok())
+                    .map(Score::TopProduct)
+                    .ok_or(format!("Unknown score '{s}'.")),
+            }
+        }
+    }
+
+    impl Score {
+        pub(crate) fn apply(&self, inspections: &[u64]) -> Scored {
+            let mut ranking = inspections.iter().copied().enumerate().collect::<Vec<_>>();
+            // Stable, so ties stay in monkey order.
+            ranking.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+
+            match self {
+                // Enough rounds can push the product of two counts past a u64.
+                Score::TopProduct(k) => Scored::Value(
+                    ranking
+                        .iter()
+                        .take(*k)
+                        .map(|&(_, count)| count as u128)
+                        .product(),
+                ),
+                Score::Sum => Scored::Value(inspections.iter().map(|&c| c as u128).sum()),
+                Score::Ranking => Scored::Ranking(ranking),
+            }
+        }
+    }
+
+    impl Display for Scored {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Scored::Value(value) => write!(f, "{value}"),
+                Scored::Ranking(ranking) => {
+                    for (monkey, count) in ranking {
+                        write!(f, "\n  Monkey {monkey}: {count}")?;
+                    }
+
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{Score, Scored};
+
+        #[test]
+        fn scoring() {
+            let counts = [101, 95, 7, 105];
+            let value = |score: Score| match score.apply(&counts) {
+                Scored::Value(value) => value,
+                Scored::Ranking(_) => panic!("Expected a value."),
+            };
+
+            assert_eq!(value("top-2".parse().unwrap()), 10605);
+            assert_eq!(value("top-3".parse().unwrap()), 101 * 95 * 105);
+            assert_eq!(value("top-9".parse().unwrap()), 101 * 95 * 7 * 105);
+            assert_eq!(value("sum".parse().unwrap()), 308);
+            assert!("top-".parse::<Score>().is_err());
+
+            let Scored::Ranking(ranking) = Score::Ranking.apply(&counts) else {
+                panic!("Expected a ranking.");
+            };
+            assert_eq!(ranking, vec![(3, 105), (0, 101), (1, 95), (2, 7)]);
+        }
+    }
+}
+
+mod modular {
+    use crate::operation::{Operator, Worry};
+    use num_bigint::BigUint;
+    use num_traits::ToPrimitive;
+
+    /// A worry level kept modulo the product of every monkey's test divisor.
+    /// Only addition and multiplication survive the reduction.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub(crate) struct Residue {
+        pub(crate) value: u64,
+        pub(crate) modulus: u64,
+    }
+
+    impl std::fmt::Display for Residue {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.value)
+        }
+    }
+
+    impl Residue {
+        pub(crate) fn new(value: &BigUint, modulus: u64) -> Self {
+            let value = u64::try_from(value % modulus).expect("Reduced below a u64 modulus.");
+            Self { value, modulus }
+        }
+    }
+
+    impl Worry for Residue {
+        fn literal(&self, value: &BigUint) -> Option<Self> {
+            // Literals are reduced up front, so this avoids any allocation.
+            match value.to_u64() {
+                Some(value) => Some(Self {
+                    value: value % self.modulus,
+                    modulus: self.modulus,
+                }),
+                None => Some(Residue::new(value, self.modulus)),
+            }
         }
 
-        if trimmed.starts_with("Operation") {
-            let operation_string = trimmed
-                .strip_prefix("Operation: new = old ")
-                .expect(INPUT_ERR);
-            let segments: Vec<&str> = operation_string.split(' ').collect();
-            let last_monkey = monkeys.last_mut().unwrap();
-            let failure = |val: &str| panic!("{OPP_ERR} -> '{val}'");
+        fn apply(&self, operator: Operator, other: &Self) -> Option<Self> {
+            let (a, b, m) = (self.value, other.value, self.modulus);
 
-            match segments[0] {
-                "*" => match segments[1] {
-                    "5" => last_monkey.operation = Box::new(|old| old * 5u8),
-                    "old" => last_monkey.operation = Box::new(|old| old.pow(2)),
-                    "7" => last_monkey.operation = Box::new(|old| old * 7u8),
-                    value => failure(value),
+            // Both sides are already below the modulus. Only a product that
+            // overflows needs widening, which keeps the common case in u64.
+            let value = match operator {
+                Operator::Add => match a.checked_add(b) {
+                    Some(sum) => sum % m,
+                    None => ((a as u128 + b as u128) % m as u128) as u64,
                 },
-                "+" => match segments[1] {
-                    "1" => last_monkey.operation = Box::new(|old| old + 1u8),
-                    "3" => last_monkey.operation = Box::new(|old| old + 3u8),
-                    "5" => last_monkey.operation = Box::new(|old| old + 5u8),
-                    "8" => last_monkey.operation = Box::new(|old| old + 8u8),
-                    "2" => last_monkey.operation = Box::new(|old| old + 2u8),
-                    value => failure(value),
+                Operator::Multiply => match a.checked_mul(b) {
+                    Some(product) => product % m,
+                    None => ((a as u128 * b as u128) % m as u128) as u64,
                 },
-                value => failure(value),
+                // The real value could have gone below zero, which a residue
+                // can't tell.
+                Operator::Subtract | Operator::Divide => return None,
+            };
+
+            Some(Self {
+                value,
+                modulus: self.modulus,
+            })
+        }
+
+        fn divisible_by(&self, divisor: u64) -> bool {
+            self.value.is_multiple_of(divisor)
+        }
+    }
+}
+
+/// Plays the given number of rounds, moving items between monkeys in place.
+/// Relief is already folded into the operations, so `reduce` only keeps
+/// worry levels small, e.g. modulo the product of the divisors. The log, if
+/// any, is told about every throw and every finished round.
+fn simulate<T: Worry>(
+    monkeys: &mut [Monkey<T>],
+    rounds: u64,
+    reduce: impl Fn(T) -> T,
+    mut log: Option<&mut Log>,
+) {
+    for round in 1..=rounds {
+        for monkey_index in 0..monkeys.len() {
+            let mut items = std::mem::take(&mut monkeys[monkey_index].items);
+            monkeys[monkey_index].inspections += items.len() as u64;
+
+            for item in items.drain(..) {
+                let monkey = &monkeys[monkey_index];
+                let worry = reduce(monkey.operation.evaluate(&item).expect(EVAL_ERR));
+
+                let target = if worry.divisible_by(monkey.test_divisor) {
+                    monkey.true_index
+                } else {
+                    monkey.false_index
+                };
+
+                if let Some(log) = log.as_deref_mut() {
+                    log.throw(round, &item, monkey_index, target, &worry);
+                }
+
+                monkeys.get_mut(target).expect(INDEX_ERR).items.push(worry);
+            }
+
+            // Hand the emptied list back so its allocation gets reused.
+            if monkeys[monkey_index].items.is_empty() {
+                monkeys[monkey_index].items = items;
             }
         }
 
-        if trimmed.starts_with("Test") {
-            let test_string = trimmed
-                .strip_prefix("Test: divisible by ")
-                .expect(INPUT_ERR);
-            let division_value: u64 = test_string.parse().expect(INPUT_ERR);
-            monkeys.last_mut().unwrap().test_divisor = division_value;
+        if let Some(log) = log.as_deref_mut() {
+            log.end_round(round, monkeys.iter().map(|m| m.inspections));
         }
+    }
+}
 
-        if trimmed.starts_with("If ") {
-            let stripped = trimmed.strip_prefix("If ").expect(INPUT_ERR);
+mod report {
+    use std::fmt::{Display, Write};
+
+    /// A single throw. When no operation divides or subtracts, worry levels
+    /// are only tracked modulo the product of the test divisors, so `item` and
+    /// `residue` are those remainders rather than the true levels. Otherwise
+    /// they're exact.
+    pub(crate) struct Throw {
+        pub(crate) round: u64,
+        /// The item's worry level before it was inspected.
+        pub(crate) item: String,
+        pub(crate) from: usize,
+        pub(crate) to: usize,
+        /// The item's worry level as it was thrown.
+        pub(crate) residue: String,
+    }
+
+    /// What happened during a simulation: inspection counts at the chosen
+    /// checkpoint rounds and, optionally, every throw.
+    pub(crate) struct Log {
+        checkpoints: Vec<u64>,
+        pub(crate) counts: Vec<(u64, Vec<u64>)>,
+        pub(crate) throws: Option<Vec<Throw>>,
+    }
+
+    impl Log {
+        pub(crate) fn new(checkpoints: Vec<u64>, record_throws: bool) -> Self {
+            Self {
+                checkpoints,
+                counts: vec![],
+                throws: record_throws.then(Vec::new),
+            }
+        }
+
+        pub(crate) fn throw<T: Display>(
+            &mut self,
+            round: u64,
+            item: &T,
+            from: usize,
+            to: usize,
+            residue: &T,
+        ) {
+            // Formatting every item is the expensive part, so skip it if unused.
+            if let Some(throws) = &mut self.throws {
+                throws.push(Throw {
+                    round,
+                    item: item.to_string(),
+                    from,
+                    to,
+                    residue: residue.to_string(),
+                });
+            }
+        }
 
-            if let Some(index) = stripped.strip_prefix("true: throw to monkey ") {
-                monkeys.last_mut().unwrap().true_index = index.parse().expect(INPUT_ERR);
+        pub(crate) fn end_round(&mut self, round: u64, inspections: impl Iterator<Item = u64>) {
+            if self.checkpoints.contains(&round) {
+                self.counts.push((round, inspections.collect()));
+            }
+        }
+
+        /// The counts in the puzzle's own format.
+        pub(crate) fn to_text(&self) -> String {
+            let mut text = String::new();
+
+            for (round, counts) in &self.counts {
+                writeln!(text, "== After round {round} ==").unwrap();
+
+                for (monkey, count) in counts.iter().enumerate() {
+                    writeln!(text, "Monkey {monkey} inspected items {count} times.").unwrap();
+                }
+
+                writeln!(text).unwrap();
+            }
+
+            text
+        }
+
+        /// One row per checkpoint and one column per monkey.
+        pub(crate) fn counts_csv(&self) -> String {
+            let monkeys = self.counts.first().map_or(0, |(_, c)| c.len());
+            let mut csv = String::from("round");
+
+            for monkey in 0..monkeys {
+                write!(csv, ",monkey {monkey}").unwrap();
+            }
+
+            for (round, counts) in &self.counts {
+                write!(csv, "\n{round}").unwrap();
+
+                for count in counts {
+                    write!(csv, ",{count}").unwrap();
+                }
+            }
+
+            csv.push('\n');
+            csv
+        }
+
+        pub(crate) fn throws_csv(&self) -> Option<String> {
+            let throws = self.throws.as_ref()?;
+            let mut csv = String::from("round,item,from,to,residue\n");
+
+            for Throw {
+                round,
+                item,
+                from,
+                to,
+                residue,
+            } in throws
+            {
+                writeln!(csv, "{round},{item},{from},{to},{residue}").unwrap();
+            }
+
+            Some(csv)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::Log;
+        use crate::{logged_inspections, parse_monkeys, tests::TEST_DATA, Monkey, Params};
+
+        fn monkeys() -> Vec<Monkey> {
+            parse_monkeys(TEST_DATA).unwrap()
+        }
+
+        #[test]
+        fn checkpoints() {
+            let mut log = Log::new(vec![1, 20, 1000], false);
+            logged_inspections(monkeys(), &Params::part_two().with_rounds(1000), &mut log);
+
+            assert!(log.to_text().starts_with(
+                "== After round 1 ==
+Monkey 0 inspected items 2 times.
+Monkey 1 inspected items 4 times.
+Monkey 2 inspected items 3 times.
+Monkey 3 inspected items 6 times.
+
+== After round 20 ==
+Monkey 0 inspected items 99 times."
+            ));
+            assert_eq!(
+                log.counts_csv(),
+                "round,monkey 0,monkey 1,monkey 2,monkey 3
+1,2,4,3,6
+20,99,97,8,103
+1000,5204,4792,199,5192
+"
+            );
+            assert!(log.throws_csv().is_none());
+        }
+
+        #[test]
+        fn throws() {
+            let mut log = Log::new(vec![], true);
+            logged_inspections(monkeys(), &Params::part_one().with_rounds(1), &mut log);
+
+            let csv = log.throws_csv().unwrap();
+            let mut lines = csv.lines();
+
+            assert_eq!(lines.next(), Some("round,item,from,to,residue"));
+            assert_eq!(lines.next(), Some("1,79,0,3,500"));
+            assert_eq!(lines.next(), Some("1,98,0,3,620"));
+            assert_eq!(lines.next(), Some("1,54,1,0,20"));
+            assert_eq!(csv.lines().count(), 1 + 10 + 4);
+        }
+    }
+}
+
+// The divisor product is the common multiple of all the testing divisors.
+// The reason using this product makes the sequence so much faster
+// is because it caps the ceiling of the integers were working with.
+//
+// The reason the common product is taken and not the greatest divisor
+// is because the common product is a multiple of all the values, whereas the
+// greatest is not.
+//
+// This allows us to trim the big integers down by removing additional groupings of
+// the common multiple for all modulus operations.
+//
+// This technique is congruent with the multiplication and addition, but not division.
+// This is why this technique can only be used when the worry divisor is 1.
+//
+// The product has to fit in a u64 for the fast path, and none of the
+// operations can divide or subtract, as neither survives the reduction.
+fn divisor_product(monkeys: &[Monkey]) -> Option<u64> {
+    if !monkeys.iter().all(|m| m.operation.reducible()) {
+        return None;
+    }
+
+    monkeys
+        .iter()
+        .try_fold(1u64, |product, m| product.checked_mul(m.test_divisor))
+}
+
+/// The monkeys with their items and literals reduced to `u64` residues.
+/// Only possible without relief.
+fn residue_monkeys(monkeys: &[Monkey]) -> Option<Vec<Monkey<Residue>>> {
+    let modulus = divisor_product(monkeys)?;
+
+    let residues = monkeys
+        .iter()
+        .map(|m| Monkey {
+            inspections: 0,
+            items: m.items.iter().map(|i| Residue::new(i, modulus)).collect(),
+            operation: m.operation.reduced(modulus),
+            test_divisor: m.test_divisor,
+            true_index: m.true_index,
+            false_index: m.false_index,
+        })
+        .collect();
+
+    Some(residues)
+}
+
+/// Inspection counts using `u64` residues, playing every round.
+fn modular_inspections(monkeys: &[Monkey], rounds: u64) -> Option<Vec<u64>> {
+    let mut residues = residue_monkeys(monkeys)?;
+    simulate(&mut residues, rounds, |item| item, None);
+    Some(residues.iter().map(|m| m.inspections).collect())
+}
+
+mod cycles {
+    use crate::{modular::Residue, Monkey, EVAL_ERR, INDEX_ERR};
+    use std::collections::HashMap;
+
+    /// Follows one item through a single round, counting the monkeys that
+    /// inspect it, and returns where it starts the next round. Throwing to a
+    /// later monkey means another inspection in the same round, while
+    /// throwing to an earlier one (or itself) means waiting for the next.
+    fn item_round(
+        monkeys: &[Monkey<Residue>],
+        (mut at, mut item): (usize, Residue),
+        counts: &mut [u64],
+    ) -> (usize, Residue) {
+        loop {
+            let monkey = &monkeys[at];
+            counts[at] += 1;
+            item = monkey.operation.evaluate(&item).expect(EVAL_ERR);
+
+            let target = if item.value.is_multiple_of(monkey.test_divisor) {
+                monkey.true_index
             } else {
-                let index = stripped.strip_prefix("false: throw to monkey ").unwrap();
-                monkeys.last_mut().unwrap().false_index = index.parse().expect(INPUT_ERR);
+                monkey.false_index
+            };
+
+            assert!(target < monkeys.len(), "{INDEX_ERR}");
+
+            if target <= at {
+                return (target, item);
             }
+
+            at = target;
         }
     }
 
-    // The divisor product is the common multiple of all the testing divisors.
-    // The reason using this product makes the sequence so much faster
-    // is because it caps the ceiling of the integers were working with.
-    //
-    // The reason the common product is taken and not the greatest divisor
-    // is because the common product is a multiple of all the values, whereas the
-    // greatest is not.
-    //
-    // This allows us to trim the big integers down by removing additional groupings of
-    // the common multiple for all modulus operations.
-    //
-    // This technique is congruent with the multiplication and addition, but not division.
-    // This is why this technique can only be used when the worry divisor is 1.
-    let divisor_product: u64 = monkeys.iter().map(|m| m.test_divisor.to_owned()).product();
+    /// Inspection counts caused by one item over the given number of rounds.
+    ///
+    /// An item only ever affects itself, and its state at the start of a round
+    /// (its monkey and its residue) is drawn from a finite set. So it has to
+    /// repeat eventually, after which every period adds the same counts.
+    fn item_inspections(
+        monkeys: &[Monkey<Residue>],
+        start: (usize, Residue),
+        rounds: u64,
+    ) -> Vec<u64> {
+        // The round each state was first seen at, and the running counts at
+        // the start of each round.
+        let mut seen = HashMap::new();
+        let mut history = vec![vec![0; monkeys.len()]];
+        let mut state = start;
 
-    for _ in 0..rounds {
-        for monkey_index in 0..monkeys.len() {
-            let current_monkey = &mut monkeys[monkey_index];
-            let items = current_monkey.items.clone();
-            current_monkey.items.clear();
-            current_monkey.inspections += items.len() as u64;
-
-            for mut item in items {
-                let monkey_ref = &monkeys[monkey_index];
-
-                // Modulo trick only works if using a worry divisor of one.
-                // This is because the trick only applies via the laws of
-                // modular multiplication and addition.
-                // Division is a different story...
-                //
-                // https://is.gd/QzvXLH
-                // https://is.gd/qCosZt
-                if worry_divider == 1 {
-                    item %= divisor_product;
-                }
+        for round in 0..rounds {
+            if let Some(&first) = seen.get(&state) {
+                let period = round - first;
+                let remaining = rounds - round;
+                let (full, partial) = (remaining / period, remaining % period);
+                let (first, round, partial) = (first as usize, round as usize, partial as usize);
+
+                return (0..monkeys.len())
+                    .map(|m| {
+                        let per_period = history[round][m] - history[first][m];
+                        let tail = history[first + partial][m] - history[first][m];
+                        history[round][m] + full * per_period + tail
+                    })
+                    .collect();
+            }
 
-                item = (monkey_ref.operation)(item);
-                item /= worry_divider;
+            seen.insert(state, round);
 
-                // BigUint doesn't enable the copy trait, so we must clone it.
-                if item.clone() % monkey_ref.test_divisor == BigUint::from(0u8) {
-                    let true_index = monkey_ref.true_index;
-                    monkeys[true_index].items.push(item);
-                } else {
-                    let false_index = monkey_ref.false_index;
-                    monkeys[false_index].items.push(item);
+            let mut counts = history.last().unwrap().clone();
+            state = item_round(monkeys, state, &mut counts);
+            history.push(counts);
+        }
+
+        history.pop().unwrap()
+    }
+
+    /// Inspection counts without playing every round, by finding each item's
+    /// cycle and extrapolating from it.
+    pub(crate) fn cyclic_inspections(monkeys: &[Monkey<Residue>], rounds: u64) -> Vec<u64> {
+        let mut totals = vec![0; monkeys.len()];
+
+        for (index, monkey) in monkeys.iter().enumerate() {
+            for item in &monkey.items {
+                let counts = item_inspections(monkeys, (index, *item), rounds);
+
+                for (total, count) in totals.iter_mut().zip(counts) {
+                    *total += count;
                 }
             }
         }
+
+        totals
+    }
+}
+
+/// Inspection counts using big integers, which works for any operation. When
+/// none of them divide or subtract, the items are still reduced, just by a
+/// product that may not fit in a u64.
+fn big_inspections(mut monkeys: Vec<Monkey>, rounds: u64, log: Option<&mut Log>) -> Vec<u64> {
+    if !monkeys.iter().all(|m| m.operation.reducible()) {
+        simulate(&mut monkeys, rounds, |item| item, log);
+    } else {
+        let product: BigUint = monkeys.iter().map(|m| m.test_divisor).product();
+        simulate(&mut monkeys, rounds, |item| item % &product, log);
+    }
+
+    monkeys.iter().map(|m| m.inspections).collect()
+}
+
+fn inspections(monkeys: Vec<Monkey>, params: &Params) -> Vec<u64> {
+    let monkeys = params.relief.apply(monkeys);
+
+    match residue_monkeys(&monkeys) {
+        Some(residues) => cyclic_inspections(&residues, params.rounds),
+        None => big_inspections(monkeys, params.rounds, None),
+    }
+}
+
+/// Plays every round like `inspections`, but records what happens in the log.
+fn logged_inspections(monkeys: Vec<Monkey>, params: &Params, log: &mut Log) -> Vec<u64> {
+    let monkeys = params.relief.apply(monkeys);
+
+    match residue_monkeys(&monkeys) {
+        Some(mut residues) => {
+            simulate(&mut residues, params.rounds, |item| item, Some(log));
+            residues.iter().map(|m| m.inspections).collect()
+        }
+        None => big_inspections(monkeys, params.rounds, Some(log)),
+    }
+}
+
+/// Times part two on both number representations.
+fn bench(input: &str, runs: u32) {
+    use std::time::Instant;
+
+    let monkeys = parse_monkeys(input).unwrap();
+
+    let start = Instant::now();
+    for _ in 0..runs {
+        std::hint::black_box(modular_inspections(&monkeys, 10000));
+    }
+    let modular = start.elapsed() / runs;
+
+    let start = Instant::now();
+    for _ in 0..runs {
+        let monkeys = parse_monkeys(input).unwrap();
+        std::hint::black_box(big_inspections(monkeys, 10000, None));
+    }
+    let big = start.elapsed() / runs;
+
+    println!("Modular u64: {modular:?} per run");
+    println!("BigUint: {big:?} per run");
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::{
+        big_inspections, cyclic_inspections, inspections, modular_inspections, operation,
+        parse_monkeys, residue_monkeys, score::Scored, Monkey, Params, ParseError, Relief, Score,
+    };
+
+    pub(crate) const TEST_DATA: &str = include_str!("test.txt");
+
+    /// The puzzle's answer: the two highest counts multiplied together.
+    fn monkey_business(monkeys: Vec<Monkey>, params: &Params) -> u128 {
+        match Score::TopProduct(2).apply(&inspections(monkeys, params)) {
+            Scored::Value(value) => value,
+            Scored::Ranking(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn part_one() {
+        let monkeys = parse_monkeys(TEST_DATA).unwrap();
+        assert_eq!(monkey_business(monkeys, &Params::part_one()), 10605);
+    }
+
+    #[test]
+    fn part_two() {
+        let monkeys = parse_monkeys(TEST_DATA).unwrap();
+        assert_eq!(monkey_business(monkeys, &Params::part_two()), 2713310158);
+    }
+
+    #[test]
+    fn relief_functions() {
+        // Spelling out part one's relief gives the same answer.
+        let relief = Relief::Function(operation::parse("old / 3").unwrap());
+        let params = Params::part_one().with_relief(relief);
+        let monkeys = parse_monkeys(TEST_DATA).unwrap();
+        assert_eq!(monkey_business(monkeys, &params), 10605);
+
+        // Relief that doesn't divide still gets the fast path.
+        let relief = Relief::Function(operation::parse("old + 1").unwrap());
+        let params = Params::part_two().with_relief(relief);
+        let monkeys = parse_monkeys(TEST_DATA).unwrap();
+        assert_eq!(
+            monkey_business(monkeys, &params.with_rounds(1000)),
+            monkey_business(
+                parse_monkeys(&TEST_DATA.replace("new = old", "new = 1 + old")).unwrap(),
+                &Params::part_two().with_rounds(1000)
+            )
+        );
+    }
+
+    #[test]
+    fn validation() {
+        let unknown =
+            TEST_DATA.replace("If false: throw to monkey 1", "If false: throw to monkey 4");
+        assert_eq!(
+            parse_monkeys(&unknown).err(),
+            Some(ParseError::UnknownTarget {
+                monkey: 3,
+                target: 4
+            })
+        );
+
+        let zero = TEST_DATA.replace("divisible by 13", "divisible by 0");
+        assert_eq!(
+            parse_monkeys(&zero).err(),
+            Some(ParseError::ZeroDivisor { monkey: 2 })
+        );
+
+        let operation = TEST_DATA.replace("old * 19", "old ^ 19");
+        assert_eq!(
+            parse_monkeys(&operation).err(),
+            Some(ParseError::Operation { line: 3 })
+        );
+
+        let items = TEST_DATA.replace("79, 98", "79, x");
+        assert_eq!(
+            parse_monkeys(&items).err(),
+            Some(ParseError::Format { line: 2 })
+        );
+
+        let order = TEST_DATA.replace("Monkey 2:", "Monkey 5:");
+        assert_eq!(
+            parse_monkeys(&order).err(),
+            Some(ParseError::Format { line: 15 })
+        );
+
+        let headless = TEST_DATA.replace("Monkey 0:\n", "");
+        assert_eq!(
+            parse_monkeys(&headless).err(),
+            Some(ParseError::Format { line: 1 })
+        );
+
+        let unknown_line = TEST_DATA.replace("Test: divisible by 19", "Test: even");
+        assert_eq!(
+            parse_monkeys(&unknown_line).err(),
+            Some(ParseError::Format { line: 11 })
+        );
+
+        let repeated = TEST_DATA.replace(
+            "Operation: new = old + 6",
+            "Operation: new = old + 6\n  Operation: new = old + 7",
+        );
+        assert_eq!(
+            parse_monkeys(&repeated).err(),
+            Some(ParseError::Format { line: 11 })
+        );
+    }
+
+    #[test]
+    fn missing_lines() {
+        let operation = TEST_DATA.replace("  Operation: new = old + 6\n", "");
+        assert_eq!(
+            parse_monkeys(&operation).err(),
+            Some(ParseError::Missing {
+                monkey: 1,
+                line: "Operation"
+            })
+        );
+
+        let target = TEST_DATA.replace("    If false: throw to monkey 1\n", "");
+        assert_eq!(
+            parse_monkeys(&target).err(),
+            Some(ParseError::Missing {
+                monkey: 3,
+                line: "If false"
+            })
+        );
+    }
+
+    #[test]
+    fn blank_lines() {
+        // Neither adds a monkey, so there's no divisor of zero to report.
+        let trailing = format!("{}\n\n\n", TEST_DATA.trim_end());
+        let doubled = format!("\n{}", TEST_DATA.replace("\n\n", "\n\n\n"));
+
+        for input in [trailing, doubled] {
+            let monkeys = parse_monkeys(&input).unwrap();
+            assert_eq!(monkeys.len(), 4);
+            assert_eq!(monkey_business(monkeys, &Params::part_one()), 10605);
+        }
+    }
+
+    #[test]
+    fn paths_agree() {
+        let monkeys = parse_monkeys(TEST_DATA).unwrap();
+        let modular = modular_inspections(&monkeys, 1000).unwrap();
+        assert_eq!(modular, vec![5204, 4792, 199, 5192]);
+        assert_eq!(big_inspections(monkeys, 1000, None), modular);
+
+        // Division can't be done on residues.
+        let dividing = TEST_DATA.replace("old + 3", "old / 2");
+        assert!(modular_inspections(&parse_monkeys(&dividing).unwrap(), 20).is_none());
+
+        // Nor can subtraction: reduced by 15, the worry here would soon drop
+        // below 7, though the real one only ever grows.
+        let subtracting = "Monkey 0:
+  Starting items: 20
+  Operation: new = old * 2 - 7
+  Test: divisible by 3
+    If true: throw to monkey 1
+    If false: throw to monkey 1
+
+Monkey 1:
+  Starting items:
+  Operation: new = old + 10
+  Test: divisible by 5
+    If true: throw to monkey 0
+    If false: throw to monkey 0";
+        let monkeys = parse_monkeys(subtracting).unwrap();
+        assert!(modular_inspections(&monkeys, 200).is_none());
+        assert_eq!(big_inspections(monkeys, 200, None), vec![200, 200]);
+
+        let monkeys = parse_monkeys(subtracting).unwrap();
+        assert_eq!(
+            inspections(monkeys, &Params::part_two().with_rounds(200)),
+            vec![200, 200]
+        );
     }
 
-    let mut sorted_inspections: Vec<u64> = monkeys.iter().map(|m| m.inspections).collect();
-    sorted_inspections.sort();
-    sorted_inspections.reverse();
+    #[test]
+    fn cycles_match_brute_force() {
+        // Items in both inputs cycle within a few hundred rounds, so the longer
+        // runs here go through the extrapolation.
+        for input in [TEST_DATA, include_str!("input.txt")] {
+            let monkeys = parse_monkeys(input).unwrap();
+            let residues = residue_monkeys(&monkeys).unwrap();
 
-    sorted_inspections[0] * sorted_inspections[1]
+            for rounds in [0, 1, 20, 1000, 10000] {
+                assert_eq!(
+                    cyclic_inspections(&residues, rounds),
+                    modular_inspections(&monkeys, rounds).unwrap()
+                );
+            }
+        }
+    }
 }
@@ -1,28 +1,58 @@
 #![allow(non_snake_case)]
 
-use parser::parse_input;
-
-const INPUT_ERR: &str = "Unexpected input.";
+use num_bigint::BigUint;
+use parser::{parse_input, parse_packet, parse_packets};
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::ops::Index;
+use trace::explain;
 
 struct Pair {
     left: Packet,
     right: Packet,
 }
 
-#[derive(std::clone::Clone, Eq, Debug)]
+#[derive(Clone, Eq, Debug)]
 enum Packet {
-    Number(u32),
+    Number(BigUint),
     Container(Vec<Packet>),
 }
 
+// A number and a container holding only that number compare as equal, so
+// equality has to come from `cmp` rather than be derived structurally.
 impl PartialEq for Packet {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Self::Number(l0), Self::Number(r0)) => l0 == r0,
-            (Self::Container(l0), Self::Container(r0)) => l0 == r0,
-            // Mixed matches forward to container to container comparison ^.
-            (Self::Number(l0), Self::Container(r0)) => &vec![Self::Number(*l0)] == r0,
-            (Self::Container(l0), Self::Number(r0)) => l0 == &vec![Self::Number(*r0)],
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Packet {
+    /// The number this packet is equal to, if any: `7`, `[7]`, `[[7]]` and so on.
+    fn as_number(&self) -> Option<&BigUint> {
+        match self {
+            Packet::Number(value) => Some(value),
+            Packet::Container(values) => match values.as_slice() {
+                [only] => only.as_number(),
+                _ => None,
+            },
+        }
+    }
+}
+
+// Hashes the same canonical form for every member of an equality class, by
+// collapsing wrapped numbers and hashing containers element by element.
+impl Hash for Packet {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match (self.as_number(), self) {
+            (Some(value), _) => {
+                state.write_u8(0);
+                value.hash(state);
+            }
+            (None, Packet::Container(values)) => {
+                state.write_u8(1);
+                values.hash(state);
+            }
+            (None, Packet::Number(_)) => unreachable!(),
         }
     }
 }
@@ -32,8 +62,10 @@ impl Ord for Packet {
         match (self, other) {
             (Packet::Number(l0), Packet::Number(r0)) => l0.cmp(r0),
             (Packet::Container(l0), Packet::Container(r0)) => l0.cmp(r0),
-            (Packet::Container(l0), Packet::Number(r0)) => l0.cmp(&vec![Packet::Number(*r0)]),
-            (Packet::Number(l0), Packet::Container(r0)) => vec![Packet::Number(*l0)].cmp(r0),
+            (Packet::Container(l0), Packet::Number(r0)) => {
+                l0.cmp(&vec![Packet::Number(r0.clone())])
+            }
+            (Packet::Number(l0), Packet::Container(r0)) => vec![Packet::Number(l0.clone())].cmp(r0),
         }
     }
 }
@@ -44,9 +76,37 @@ impl PartialOrd for Packet {
     }
 }
 
+impl Display for Packet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Packet::Number(value) => write!(f, "{value}"),
+            Packet::Container(values) => {
+                write!(f, "[")?;
+
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+
+                    write!(f, "{value}")?;
+                }
+
+                write!(f, "]")
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{correctly_ordered_index_sum, parser::parse_input};
+    use crate::{
+        correctly_ordered_index_sum, divider_packets_index_product,
+        divider_packets_index_product_sorted,
+        parser::{parse_input, parse_packet, tests::packet},
+        rank_of, Packet, Pair, PairPackets,
+    };
+    use proptest::prelude::*;
+    use std::cmp::Ordering;
 
     const TEST_DATA: &str = "[1,1,3,1,1]
 [1,1,5,1,1]
@@ -74,174 +134,888 @@ mod tests {
 
     #[test]
     fn example() {
-        let parsed = parse_input(TEST_DATA);
-        let sum = correctly_ordered_index_sum(&parsed);
-        assert_eq!(sum, 13);
+        let parsed = parse_input(TEST_DATA).unwrap();
+        let result = correctly_ordered_index_sum(&parsed);
+        assert_eq!(result.index_sum, 13);
+        assert!(result.equal.is_empty());
     }
 
     #[test]
     fn compare() {
         let input = "[[1],[2,3,4]]\n[[1],4]";
-        let parsed = parse_input(input);
+        let parsed = parse_input(input).unwrap();
         let only = parsed.first().unwrap();
         assert_eq!(only.left.cmp(&only.right), std::cmp::Ordering::Less);
     }
+
+    #[test]
+    fn dividers() {
+        let parsed = parse_input(TEST_DATA).unwrap();
+        assert_eq!(divider_packets_index_product(&parsed), 140);
+        assert_eq!(divider_packets_index_product_sorted(&parsed), 140);
+    }
+
+    #[test]
+    fn ranking() {
+        let parsed = parse_input(TEST_DATA).unwrap();
+        let corpus = PairPackets(&parsed);
+
+        assert_eq!(rank_of(&parse_packet("[]").unwrap(), &corpus), 0);
+        assert_eq!(rank_of(&parse_packet("[[2]]").unwrap(), &corpus), 9);
+        assert_eq!(rank_of(&parse_packet("[10]").unwrap(), &corpus), 16);
+        assert_eq!(corpus[3].to_string(), "[[1],4]");
+    }
+
+    #[test]
+    fn equal_pairs_are_reported() {
+        let parsed = parse_input("[1]\n[2]\n\n[3]\n[[3]]\n\n[[]]\n[]").unwrap();
+        let result = correctly_ordered_index_sum(&parsed);
+        assert_eq!(result.index_sum, 1);
+        assert_eq!(result.equal, vec![2]);
+    }
+
+    #[test]
+    fn mixed_equality_hashes() {
+        use std::collections::HashSet;
+
+        let set = ["5", "[5]", "[[[5]]]", "[[5],[[6]]]", "[5,6]", "[[]]", "[]"]
+            .iter()
+            .map(|s| parse_packet(s).unwrap())
+            .collect::<HashSet<_>>();
+
+        assert_eq!(set.len(), 4);
+        assert!(set.contains(&parse_packet("[[5]]").unwrap()));
+        assert!(set.contains(&parse_packet("[[5],6]").unwrap()));
+    }
+
+    fn hash_of(packet: &Packet) -> u64 {
+        use std::hash::{BuildHasher, RandomState};
+
+        // Fixed for the whole test run, so hashes are comparable.
+        thread_local!(static STATE: RandomState = RandomState::new());
+        STATE.with(|state| state.hash_one(packet))
+    }
+
+    /// Small packets from a tiny alphabet, so equal and nearly equal packets
+    /// come up often, including numbers wrapped in single-element containers.
+    fn small_packet() -> impl Strategy<Value = Packet> {
+        let leaf = (0u8..3).prop_map(|n| Packet::Number(n.into()));
+
+        leaf.prop_recursive(3, 12, 3, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..3).prop_map(Packet::Container),
+                inner.prop_map(|p| Packet::Container(vec![p])),
+            ]
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(2000))]
+
+        #[test]
+        fn reflexive(a in small_packet()) {
+            prop_assert_eq!(a.cmp(&a), Ordering::Equal);
+            prop_assert!(a == a);
+        }
+
+        #[test]
+        fn antisymmetric(a in small_packet(), b in small_packet()) {
+            prop_assert_eq!(a.cmp(&b), b.cmp(&a).reverse());
+        }
+
+        #[test]
+        fn transitive(a in small_packet(), b in small_packet(), c in small_packet()) {
+            if a <= b && b <= c {
+                prop_assert!(a <= c);
+            }
+            if a == b && b == c {
+                prop_assert!(a == c);
+            }
+        }
+
+        #[test]
+        fn eq_agrees_with_cmp(a in small_packet(), b in small_packet()) {
+            prop_assert_eq!(a == b, a.cmp(&b).is_eq());
+            prop_assert_eq!(a.partial_cmp(&b), Some(a.cmp(&b)));
+        }
+
+        #[test]
+        fn hash_agrees_with_eq(a in small_packet(), b in small_packet()) {
+            if a == b {
+                prop_assert_eq!(hash_of(&a), hash_of(&b));
+            }
+        }
+
+        #[test]
+        fn wrapping_keeps_equality(a in small_packet()) {
+            let wrapped = Packet::Container(vec![a.clone()]);
+
+            if a.as_number().is_some() {
+                prop_assert_eq!(&wrapped, &a);
+                prop_assert_eq!(hash_of(&wrapped), hash_of(&a));
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn rank_matches_sorting(packets in prop::collection::vec(packet(), 0..20), probe in packet()) {
+            let mut sorted = packets.clone();
+            sorted.sort();
+
+            let rank = rank_of(&probe, &packets[..]);
+            prop_assert_eq!(rank, sorted.partition_point(|p| p < &probe));
+
+            // Copies of a divider would make the sorted search ambiguous.
+            let dividers = [parse_packet("[[2]]").unwrap(), parse_packet("[[6]]").unwrap()];
+            prop_assume!(packets.iter().all(|p| !dividers.contains(p)));
+
+            let pairs = packets
+                .chunks_exact(2)
+                .map(|c| Pair { left: c[0].clone(), right: c[1].clone() })
+                .collect::<Vec<_>>();
+            prop_assert_eq!(
+                divider_packets_index_product(&pairs),
+                divider_packets_index_product_sorted(&pairs)
+            );
+        }
+    }
 }
 
 mod parser {
-    use super::INPUT_ERR;
     use crate::{Packet, Pair};
+    use num_bigint::BigUint;
+    use std::fmt::Display;
 
-    fn pair_strings(input: &str) -> Vec<(&str, &str)> {
-        input
-            .split("\n\n")
-            // This handles trimming the whitespace off
-            // the ends automatically.
-            .map(|s| s.split_once('\n').expect(INPUT_ERR))
-            .collect()
+    #[derive(Debug, PartialEq, Eq)]
+    pub(crate) enum ParseErrorKind {
+        UnexpectedEnd,
+        UnexpectedCharacter(char),
+        /// A pair is missing its right packet.
+        UnpairedPacket,
+        /// A pair has more than two packets before the next blank line.
+        ExtraPacket,
+        /// Brackets nested deeper than `MAX_DEPTH`.
+        TooDeep,
     }
 
-    fn scope_into(mut values: &mut Vec<Packet>, mut depth: usize, last: bool) -> &mut Vec<Packet> {
-        while depth > 0 {
-            depth -= 1;
+    /// What went wrong, and the byte offset into the input where it did.
+    #[derive(Debug, PartialEq, Eq)]
+    pub(crate) struct ParseError {
+        pub(crate) offset: usize,
+        pub(crate) kind: ParseErrorKind,
+    }
 
-            let optional = if last {
-                values.last_mut()
-            } else {
-                values.first_mut()
-            };
+    impl Display for ParseError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self.kind {
+                ParseErrorKind::UnexpectedEnd => {
+                    write!(f, "Unexpected end of input at byte {}.", self.offset)
+                }
+                ParseErrorKind::UnexpectedCharacter(c) => {
+                    write!(f, "Unexpected {c:?} at byte {}.", self.offset)
+                }
+                ParseErrorKind::UnpairedPacket => {
+                    write!(f, "The packet at byte {} has no partner.", self.offset)
+                }
+                ParseErrorKind::ExtraPacket => write!(
+                    f,
+                    "The packet at byte {} is a third in its pair.",
+                    self.offset
+                ),
+                ParseErrorKind::TooDeep => write!(
+                    f,
+                    "The list at byte {} is nested more than {MAX_DEPTH} deep.",
+                    self.offset
+                ),
+            }
+        }
+    }
+
+    impl std::error::Error for ParseError {}
+
+    /// How many lists deep a packet can go. Each level is a step of recursion,
+    /// so without a limit a long enough run of `[` would overflow the stack.
+    pub(crate) const MAX_DEPTH: usize = 256;
+
+    // A recursive descent over the bytes. Whitespace (including CR and LF) is
+    // allowed between any two tokens.
+    struct Parser<'a> {
+        input: &'a str,
+        offset: usize,
+        depth: usize,
+    }
 
-            if let Packet::Container(content) = optional.unwrap() {
-                values = content;
-                continue;
+    impl<'a> Parser<'a> {
+        fn new(input: &'a str) -> Self {
+            Parser {
+                input,
+                offset: 0,
+                depth: 0,
             }
+        }
 
-            // Value must have been a `Node::Number` to reach this point.
-            panic!("A last value in the node tree is a number, not a container.");
+        fn peek(&self) -> Option<char> {
+            self.input[self.offset..].chars().next()
         }
 
-        values
-    }
+        fn error(&self) -> ParseError {
+            ParseError {
+                offset: self.offset,
+                kind: match self.peek() {
+                    Some(c) => ParseErrorKind::UnexpectedCharacter(c),
+                    None => ParseErrorKind::UnexpectedEnd,
+                },
+            }
+        }
 
-    // Things that trip this function up:
-    // * Newlines
-    // * Interspersed whitespace
-    //      * E.g. ' 2' will cause a panic.
-    fn nodify(input: &str) -> Packet {
-        let mut nodes = vec![];
-        let mut depth = 0;
-        let mut number_buffer: String = "".to_string();
+        fn skip_whitespace(&mut self) {
+            let rest = &self.input[self.offset..];
+            self.offset += rest.len() - rest.trim_start().len();
+        }
 
-        for character in input.chars() {
-            match character {
-                '[' => {
-                    scope_into(&mut nodes, depth, true).push(Packet::Container(vec![]));
-                    depth += 1;
-                }
-                ']' => {
-                    // Check if the previous buffer contains a number
-                    if number_buffer
-                        .chars()
-                        .next()
-                        .map(|s| s.is_numeric())
-                        .unwrap_or(false)
-                    {
-                        let node =
-                            Packet::Number(number_buffer.to_string().parse().expect(INPUT_ERR));
-                        number_buffer.clear();
-                        scope_into(&mut nodes, depth, true).push(node);
-                    }
+        fn packet(&mut self) -> Result<Packet, ParseError> {
+            self.skip_whitespace();
 
-                    if depth == 0 {
-                        panic!("Unmatched closing bracket '[' found.");
-                    }
+            match self.peek() {
+                Some('[') => self.container(),
+                Some(c) if c.is_ascii_digit() => Ok(self.number()),
+                _ => Err(self.error()),
+            }
+        }
 
-                    depth -= 1;
-                }
-                value => {
-                    if value == ',' {
-                        if !number_buffer.is_empty() {
-                            // A new container node will be created for the single element.
-                            let node =
-                                Packet::Number(number_buffer.to_string().parse().expect(INPUT_ERR));
-                            scope_into(&mut nodes, depth, true).push(node);
-                            number_buffer.clear();
-                        }
-
-                        continue;
-                    }
+        fn container(&mut self) -> Result<Packet, ParseError> {
+            if self.depth == MAX_DEPTH {
+                return Err(ParseError {
+                    offset: self.offset,
+                    kind: ParseErrorKind::TooDeep,
+                });
+            }
+
+            self.depth += 1;
+            let container = self.values();
+            self.depth -= 1;
+            container
+        }
+
+        fn values(&mut self) -> Result<Packet, ParseError> {
+            // Skip the opening bracket.
+            self.offset += 1;
+            self.skip_whitespace();
+
+            let mut values = vec![];
+
+            if self.peek() == Some(']') {
+                self.offset += 1;
+                return Ok(Packet::Container(values));
+            }
 
-                    number_buffer.push(value);
+            loop {
+                values.push(self.packet()?);
+                self.skip_whitespace();
+
+                match self.peek() {
+                    Some(',') => self.offset += 1,
+                    Some(']') => {
+                        self.offset += 1;
+                        return Ok(Packet::Container(values));
+                    }
+                    _ => return Err(self.error()),
                 }
             }
         }
 
-        nodes
-            .into_iter()
-            .next()
-            .expect("Why was an empty line parsed?")
+        fn number(&mut self) -> Packet {
+            let rest = &self.input[self.offset..];
+            let length = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            self.offset += length;
+
+            // Only digits were taken, so this can't fail.
+            Packet::Number(BigUint::parse_bytes(&rest.as_bytes()[..length], 10).unwrap())
+        }
+    }
+
+    /// Parses exactly one packet, allowing whitespace around it.
+    pub(crate) fn parse_packet(input: &str) -> Result<Packet, ParseError> {
+        let mut parser = Parser::new(input);
+        let packet = parser.packet()?;
+        parser.skip_whitespace();
+
+        match parser.peek() {
+            None => Ok(packet),
+            Some(_) => Err(parser.error()),
+        }
+    }
+
+    /// Parses every packet in the input, in order. Line breaks are treated
+    /// like any other whitespace, so blank lines and CRLF don't matter.
+    pub(crate) fn parse_packets(input: &str) -> Result<Vec<(usize, Packet)>, ParseError> {
+        let mut parser = Parser::new(input);
+        let mut packets = vec![];
+
+        loop {
+            parser.skip_whitespace();
+
+            if parser.peek().is_none() {
+                return Ok(packets);
+            }
+
+            let offset = parser.offset;
+            packets.push((offset, parser.packet()?));
+        }
     }
 
-    pub(crate) fn parse_input(input: &str) -> Vec<Pair> {
-        let line_pairs = pair_strings(input);
+    /// Parses pairs of packets, each pair separated from the next by one or
+    /// more blank lines.
+    pub(crate) fn parse_input(input: &str) -> Result<Vec<Pair>, ParseError> {
         let mut pairs = vec![];
+        let mut block = None;
+        let mut offset = 0;
 
-        for set in line_pairs {
-            let pair = Pair {
-                left: nodify(set.0),
-                right: nodify(set.1),
-            };
+        // A trailing blank line closes the last block like any other.
+        for line in input.split_inclusive('\n').chain(["\n"]) {
+            match (line.trim().is_empty(), block) {
+                (false, None) => block = Some(offset),
+                (true, Some(start)) => {
+                    pairs.push(parse_pair(&input[start..offset], start)?);
+                    block = None;
+                }
+                _ => {}
+            }
 
-            pairs.push(pair);
+            offset += line.len();
         }
 
-        pairs
+        Ok(pairs)
+    }
+
+    // Parses a block that starts `start` bytes into the input.
+    fn parse_pair(block: &str, start: usize) -> Result<Pair, ParseError> {
+        let at = |offset: usize, kind| ParseError {
+            offset: start + offset,
+            kind,
+        };
+
+        let packets = parse_packets(block).map_err(|error| at(error.offset, error.kind))?;
+
+        match <[_; 2]>::try_from(packets) {
+            Ok([(_, left), (_, right)]) => Ok(Pair { left, right }),
+            Err(packets) if packets.len() < 2 => {
+                Err(at(packets[0].0, ParseErrorKind::UnpairedPacket))
+            }
+            Err(packets) => Err(at(packets[2].0, ParseErrorKind::ExtraPacket)),
+        }
     }
 
     #[cfg(test)]
-    mod tests {
-        use super::nodify;
+    pub(crate) mod tests {
+        use super::{parse_input, parse_packet, ParseError, ParseErrorKind, MAX_DEPTH};
         use crate::Packet;
+        use num_bigint::BigUint;
+        use proptest::prelude::*;
 
         #[test]
         fn parsing() {
             use Packet::*;
 
             let string = "[[[2],3,[],[]]]";
-            let node = nodify(string);
+            let node = parse_packet(string).unwrap();
 
             let expected = Container(vec![Container(vec![
-                Container(vec![Number(2)]),
-                Number(3),
+                Container(vec![Number(2u32.into())]),
+                Number(3u32.into()),
                 Container(vec![]),
                 Container(vec![]),
             ])]);
 
             assert_eq!(node, expected);
         }
+
+        #[test]
+        fn whitespace_and_large_numbers() {
+            let spaced =
+                parse_packet(" [ 1 ,\r\n[ ] , 123456789012345678901234567890 ]\r\n").unwrap();
+            let large = "123456789012345678901234567890".parse::<BigUint>().unwrap();
+
+            assert_eq!(
+                spaced,
+                Packet::Container(vec![
+                    Packet::Number(1u32.into()),
+                    Packet::Container(vec![]),
+                    Packet::Number(large),
+                ])
+            );
+
+            let pairs = parse_input("[1]\r\n[2]\r\n\r\n[3]\r\n[4]\r\n").unwrap();
+            assert_eq!(pairs.len(), 2);
+            assert_eq!(pairs[1].right.to_string(), "[4]");
+        }
+
+        #[test]
+        fn errors_have_offsets() {
+            let error = |kind, offset| Err(ParseError { offset, kind });
+
+            assert_eq!(
+                parse_packet("[1,,2]"),
+                error(ParseErrorKind::UnexpectedCharacter(','), 3)
+            );
+            assert_eq!(
+                parse_packet("[1,[2]"),
+                error(ParseErrorKind::UnexpectedEnd, 6)
+            );
+            assert_eq!(
+                parse_packet("[1] x"),
+                error(ParseErrorKind::UnexpectedCharacter('x'), 4)
+            );
+            assert!(matches!(
+                parse_input("[1]\n[2]\n\n[3]"),
+                Err(ParseError {
+                    offset: 9,
+                    kind: ParseErrorKind::UnpairedPacket
+                })
+            ));
+        }
+
+        #[test]
+        fn pairs_follow_blank_lines() {
+            let pairs = parse_input("\n[1]\n[2]\n\n\n\n[3]\n[4]\n \n").unwrap();
+            assert_eq!(pairs.len(), 2);
+            assert_eq!(pairs[1].left.to_string(), "[3]");
+
+            // A missing packet no longer shifts every later pair along.
+            assert_eq!(
+                parse_input("[1]\n\n[2]\n[3]").err(),
+                Some(ParseError {
+                    offset: 0,
+                    kind: ParseErrorKind::UnpairedPacket
+                })
+            );
+            assert_eq!(
+                parse_input("[1]\n[2]\n\n[3]\n[4]\n[5]\n\n[6]\n[7]").err(),
+                Some(ParseError {
+                    offset: 17,
+                    kind: ParseErrorKind::ExtraPacket
+                })
+            );
+            assert_eq!(
+                parse_input("[1]\n[2]\n\n[3]\n[4,]").err(),
+                Some(ParseError {
+                    offset: 16,
+                    kind: ParseErrorKind::UnexpectedCharacter(']')
+                })
+            );
+        }
+
+        #[test]
+        fn nesting_is_limited() {
+            let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+
+            assert!(parse_packet(&nested(MAX_DEPTH)).is_ok());
+            assert_eq!(
+                parse_packet(&nested(MAX_DEPTH + 1)),
+                Err(ParseError {
+                    offset: MAX_DEPTH,
+                    kind: ParseErrorKind::TooDeep
+                })
+            );
+
+            // Far deeper than the stack could ever take.
+            let error = parse_packet(&"[".repeat(1_000_000)).unwrap_err();
+            assert_eq!(error.kind, ParseErrorKind::TooDeep);
+        }
+
+        /// Random packets, including numbers too large for any primitive.
+        pub(crate) fn packet() -> impl Strategy<Value = Packet> {
+            let leaf = prop_oneof![
+                any::<u8>().prop_map(|n| Packet::Number(n.into())),
+                "[0-9]{1,40}".prop_map(|digits| Packet::Number(digits.parse().unwrap())),
+            ];
+
+            leaf.prop_recursive(4, 64, 6, |inner| {
+                prop::collection::vec(inner, 0..6).prop_map(Packet::Container)
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn display_round_trips(packet in packet()) {
+                prop_assert_eq!(parse_packet(&packet.to_string()), Ok(packet));
+            }
+
+            #[test]
+            fn whitespace_is_ignored(packet in packet(), space in "[ \t\r\n]{0,3}") {
+                let spaced = packet
+                    .to_string()
+                    .replace(',', &format!("{space},{space}"))
+                    .replace('[', &format!("[{space}"));
+                prop_assert_eq!(parse_packet(&spaced), Ok(packet));
+            }
+
+            #[test]
+            fn never_panics(input in "[\\[\\], 0-9\r\nx]{0,24}") {
+                // Anything that does parse has to print back to an equal packet.
+                if let Ok(packet) = parse_packet(&input) {
+                    prop_assert_eq!(parse_packet(&packet.to_string()), Ok(packet));
+                }
+            }
+        }
     }
 }
 
-fn correctly_ordered_index_sum(data: &[Pair]) -> usize {
-    data.iter()
-        .enumerate()
-        .flat_map(|(i, Pair { left, right })| match left.cmp(right) {
-            std::cmp::Ordering::Less => Some(i), // Correct order for part one.
-            std::cmp::Ordering::Equal => panic!("Equal comparison should never happen."),
-            std::cmp::Ordering::Greater => None, // Discard results where left > right
-        })
-        .map(|i| i + 1)
-        .sum::<usize>()
+mod json {
+    use crate::Packet;
+    use serde_json::{Number, Value};
+    use std::fmt::Display;
+
+    /// A JSON value that has no packet equivalent, e.g. a string, a float or a
+    /// negative number.
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct NotAPacket(pub(crate) Value);
+
+    impl Display for NotAPacket {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{} is not a packet.", self.0)
+        }
+    }
+
+    impl std::error::Error for NotAPacket {}
+
+    impl From<&Packet> for Value {
+        fn from(packet: &Packet) -> Self {
+            match packet {
+                // Arbitrary precision keeps numbers past u64 intact.
+                Packet::Number(value) => Value::Number(
+                    value
+                        .to_string()
+                        .parse::<Number>()
+                        .expect("Digits are always a valid JSON number."),
+                ),
+                Packet::Container(values) => Value::Array(values.iter().map(Value::from).collect()),
+            }
+        }
+    }
+
+    impl TryFrom<&Value> for Packet {
+        type Error = NotAPacket;
+
+        fn try_from(value: &Value) -> Result<Self, Self::Error> {
+            match value {
+                Value::Number(number) => number
+                    .to_string()
+                    .parse()
+                    .map(Packet::Number)
+                    .map_err(|_| NotAPacket(value.clone())),
+                Value::Array(values) => values
+                    .iter()
+                    .map(Packet::try_from)
+                    .collect::<Result<_, _>>()
+                    .map(Packet::Container),
+                _ => Err(NotAPacket(value.clone())),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::NotAPacket;
+        use crate::parser::{parse_packet, tests::packet};
+        use crate::Packet;
+        use proptest::prelude::*;
+        use serde_json::{json, Value};
+
+        #[test]
+        fn conversion() {
+            let packet = parse_packet("[1,[2,3],[],123456789012345678901234567890]").unwrap();
+            let value = Value::from(&packet);
+
+            assert_eq!(
+                value.to_string(),
+                "[1,[2,3],[],123456789012345678901234567890]"
+            );
+            assert_eq!(Packet::try_from(&value), Ok(packet));
+
+            assert_eq!(
+                Packet::try_from(&json!([1, "2"])),
+                Err(NotAPacket(json!("2")))
+            );
+            assert!(Packet::try_from(&json!([-1])).is_err());
+            assert!(Packet::try_from(&json!([1.5])).is_err());
+        }
+
+        proptest! {
+            #[test]
+            fn json_round_trips(packet in packet()) {
+                let value = Value::from(&packet);
+                prop_assert_eq!(value.to_string(), packet.to_string());
+                prop_assert_eq!(Packet::try_from(&value), Ok(packet));
+            }
+        }
+    }
+}
+
+mod trace {
+    use crate::Packet;
+    use std::cmp::Ordering;
+    use std::fmt::Display;
+
+    /// One line of an explanation and the steps it led to.
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct Step {
+        pub(crate) message: String,
+        pub(crate) children: Vec<Step>,
+    }
+
+    impl Step {
+        fn new(message: String) -> Self {
+            Self {
+                message,
+                children: vec![],
+            }
+        }
+
+        fn write(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+            writeln!(f, "{}- {}", "  ".repeat(depth), self.message)?;
+
+            for child in &self.children {
+                child.write(f, depth + 1)?;
+            }
+
+            Ok(())
+        }
+    }
+
+    impl Display for Step {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            self.write(f, 0)
+        }
+    }
+
+    /// Compares two packets like `Ord` does, recording every step on the way.
+    pub(crate) fn explain(left: &Packet, right: &Packet) -> (Ordering, Step) {
+        let mut steps = vec![];
+        let ordering = compare(left, right, &mut steps);
+
+        // The top level is always a single comparison.
+        (ordering, steps.remove(0))
+    }
+
+    fn verdict(ordering: Ordering, reason: &str) -> Option<String> {
+        match ordering {
+            Ordering::Less => Some(format!("{reason}, so inputs are in the right order")),
+            Ordering::Greater => Some(format!("{reason}, so inputs are not in the right order")),
+            Ordering::Equal => None,
+        }
+    }
+
+    fn compare(left: &Packet, right: &Packet, steps: &mut Vec<Step>) -> Ordering {
+        match (left, right) {
+            (Packet::Number(l), Packet::Number(r)) => {
+                let mut step = Step::new(format!("Compare {left} vs {right}"));
+                let ordering = l.cmp(r);
+                let reason = match ordering {
+                    Ordering::Less => "Left side is smaller",
+                    _ => "Right side is smaller",
+                };
+
+                step.children
+                    .extend(verdict(ordering, reason).map(Step::new));
+                steps.push(step);
+                ordering
+            }
+            (Packet::Container(l), Packet::Container(r)) => {
+                let mut step = Step::new(format!("Compare {left} vs {right}"));
+                let mut ordering = Ordering::Equal;
+
+                for (a, b) in l.iter().zip(r) {
+                    ordering = compare(a, b, &mut step.children);
+
+                    if ordering.is_ne() {
+                        break;
+                    }
+                }
+
+                if ordering.is_eq() {
+                    ordering = l.len().cmp(&r.len());
+                    let reason = match ordering {
+                        Ordering::Less => "Left side ran out of items",
+                        _ => "Right side ran out of items",
+                    };
+
+                    step.children
+                        .extend(verdict(ordering, reason).map(Step::new));
+                }
+
+                steps.push(step);
+                ordering
+            }
+            (Packet::Number(_), Packet::Container(_)) => {
+                let converted = Packet::Container(vec![left.clone()]);
+                let mut step = Step::new(format!("Compare {left} vs {right}"));
+                step.children.push(Step::new(format!(
+                    "Mixed types; convert left to {converted} and retry comparison"
+                )));
+
+                let ordering = compare(&converted, right, &mut step.children);
+                steps.push(step);
+                ordering
+            }
+            (Packet::Container(_), Packet::Number(_)) => {
+                let converted = Packet::Container(vec![right.clone()]);
+                let mut step = Step::new(format!("Compare {left} vs {right}"));
+                step.children.push(Step::new(format!(
+                    "Mixed types; convert right to {converted} and retry comparison"
+                )));
+
+                let ordering = compare(left, &converted, &mut step.children);
+                steps.push(step);
+                ordering
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::explain;
+        use crate::parser::{parse_packet, tests::packet};
+        use proptest::prelude::*;
+
+        #[test]
+        fn matches_the_puzzle() {
+            let left = parse_packet("[[1],[2,3,4]]").unwrap();
+            let right = parse_packet("[[1],4]").unwrap();
+            let (ordering, step) = explain(&left, &right);
+
+            assert!(ordering.is_lt());
+            assert_eq!(
+                step.to_string(),
+                "- Compare [[1],[2,3,4]] vs [[1],4]
+  - Compare [1] vs [1]
+    - Compare 1 vs 1
+  - Compare [2,3,4] vs 4
+    - Mixed types; convert right to [4] and retry comparison
+    - Compare [2,3,4] vs [4]
+      - Compare 2 vs 4
+        - Left side is smaller, so inputs are in the right order
+"
+            );
+
+            let left = parse_packet("[7,7,7,7]").unwrap();
+            let right = parse_packet("[7,7,7]").unwrap();
+            let (ordering, step) = explain(&left, &right);
+
+            assert!(ordering.is_gt());
+            assert_eq!(
+                step.children.last().unwrap().message,
+                "Right side ran out of items, so inputs are not in the right order"
+            );
+        }
+
+        proptest! {
+            #[test]
+            fn agrees_with_cmp(left in packet(), right in packet()) {
+                prop_assert_eq!(explain(&left, &right).0, left.cmp(&right));
+            }
+        }
+    }
+}
+
+/// Part one's answer, plus any pairs whose packets are equal. The puzzle
+/// never has any, and they count as neither ordered nor unordered.
+struct OrderedPairs {
+    index_sum: usize,
+    equal: Vec<usize>,
+}
+
+fn correctly_ordered_index_sum(data: &[Pair]) -> OrderedPairs {
+    let mut result = OrderedPairs {
+        index_sum: 0,
+        equal: vec![],
+    };
+
+    for (i, Pair { left, right }) in data.iter().enumerate() {
+        match left.cmp(right) {
+            std::cmp::Ordering::Less => result.index_sum += i + 1, // Correct order for part one.
+            std::cmp::Ordering::Equal => result.equal.push(i + 1),
+            std::cmp::Ordering::Greater => (), // Discard results where left > right
+        }
+    }
+
+    result
+}
+
+/// An indexable collection of packets to rank against.
+trait Corpus: Index<usize, Output = Packet> {
+    fn len(&self) -> usize;
+}
+
+impl Corpus for [Packet] {
+    fn len(&self) -> usize {
+        <[Packet]>::len(self)
+    }
+}
+
+/// Every packet of every pair, in input order, without copying them out.
+struct PairPackets<'a>(&'a [Pair]);
+
+impl Index<usize> for PairPackets<'_> {
+    type Output = Packet;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        let pair = &self.0[index / 2];
+
+        match index % 2 {
+            0 => &pair.left,
+            _ => &pair.right,
+        }
+    }
+}
+
+impl Corpus for PairPackets<'_> {
+    fn len(&self) -> usize {
+        self.0.len() * 2
+    }
+}
+
+/// The number of packets in the corpus that sort before the given one, which
+/// is its 0-based index if it were inserted and the corpus sorted.
+fn rank_of<C: Corpus + ?Sized>(packet: &Packet, corpus: &C) -> usize {
+    (0..corpus.len()).filter(|&i| corpus[i] < *packet).count()
 }
 
 fn divider_packets_index_product(data: &[Pair]) -> usize {
-    let div_1 = Packet::Container(vec![Packet::Container(vec![Packet::Number(2)])]);
-    let div_2 = Packet::Container(vec![Packet::Container(vec![Packet::Number(6)])]);
+    let div_1 = parse_packet("[[2]]").unwrap();
+    let div_2 = parse_packet("[[6]]").unwrap();
+    let corpus = PairPackets(data);
+
+    // Both ranks in one pass. Anything below the first divider is also below
+    // the second.
+    let (mut below_1, mut below_2) = (0, 0);
+
+    for i in 0..corpus.len() {
+        if corpus[i] < div_2 {
+            below_2 += 1;
+
+            if corpus[i] < div_1 {
+                below_1 += 1;
+            }
+        }
+    }
+
+    // The second divider is also preceded by the first.
+    (below_1 + 1) * (below_2 + 2)
+}
+
+/// The original approach: sort a copy of everything and look for the dividers.
+fn divider_packets_index_product_sorted(data: &[Pair]) -> usize {
+    let div_1 = parse_packet("[[2]]").unwrap();
+    let div_2 = parse_packet("[[6]]").unwrap();
 
     let mut copy = data
         .iter()
         .flat_map(|p| [p.left.clone(), p.right.clone()])
-        .chain([div_1.clone(), div_2.clone()].into_iter())
+        .chain([div_1.clone(), div_2.clone()])
         .collect::<Vec<Packet>>();
 
     copy.sort();
@@ -253,13 +1027,120 @@ fn divider_packets_index_product(data: &[Pair]) -> usize {
         .product::<usize>()
 }
 
+/// Times the counting and sorting approaches to part two against each other.
+fn bench(data: &[Pair], runs: u32) {
+    use std::time::Instant;
+
+    let start = Instant::now();
+    for _ in 0..runs {
+        std::hint::black_box(divider_packets_index_product(std::hint::black_box(data)));
+    }
+    let counting = start.elapsed() / runs;
+
+    let start = Instant::now();
+    for _ in 0..runs {
+        std::hint::black_box(divider_packets_index_product_sorted(std::hint::black_box(
+            data,
+        )));
+    }
+    let sorting = start.elapsed() / runs;
+
+    let corpus = PairPackets(data);
+    let dividers = [
+        parse_packet("[[2]]").unwrap(),
+        parse_packet("[[6]]").unwrap(),
+    ];
+    let start = Instant::now();
+    for _ in 0..runs {
+        std::hint::black_box(
+            (rank_of(&dividers[0], &corpus) + 1) * (rank_of(&dividers[1], &corpus) + 2),
+        );
+    }
+    let ranking = start.elapsed() / runs;
+
+    println!("Counting: {counting:?} per run");
+    println!("Ranking each divider: {ranking:?} per run");
+    println!("Sorting: {sorting:?} per run");
+}
+
+/// Prints every packet in the input in ascending order, one per line, or as a
+/// single JSON array.
+fn print_sorted(input: &str, as_json: bool) {
+    let mut packets = parse_packets(input)
+        .unwrap_or_else(|error| panic!("Failed to parse packets. {error}"))
+        .into_iter()
+        .map(|(_, packet)| packet)
+        .collect::<Vec<_>>();
+
+    packets.sort();
+
+    if as_json {
+        let array = serde_json::Value::Array(packets.iter().map(serde_json::Value::from).collect());
+        println!("{array}");
+    } else {
+        for packet in packets {
+            println!("{packet}");
+        }
+    }
+}
+
 fn main() {
     let input = include_str!("input.txt");
-    let data = parse_input(input);
+    let args = std::env::args().collect::<Vec<_>>();
+
+    // `--sort [file]` sorts the packets in a file, or the puzzle input.
+    if let Some(index) = args.iter().position(|a| a == "--sort") {
+        let as_json = args.iter().any(|a| a == "--json");
+
+        match args.get(index + 1).filter(|a| !a.starts_with("--")) {
+            Some(path) => {
+                let file = std::fs::read_to_string(path).expect("Failed to read packet file.");
+                print_sorted(&file, as_json);
+            }
+            None => print_sorted(input, as_json),
+        }
+
+        return;
+    }
+
+    // `--explain <pair index>` walks through one comparison, 1-indexed like the puzzle.
+    if let Some(index) = args.iter().position(|a| a == "--explain") {
+        let data =
+            parse_input(input).unwrap_or_else(|error| panic!("Failed to parse input. {error}"));
+        let number = args
+            .get(index + 1)
+            .and_then(|a| a.parse::<usize>().ok())
+            .expect("Expected a pair index after --explain.");
+        let Pair { left, right } = number
+            .checked_sub(1)
+            .and_then(|i| data.get(i))
+            .unwrap_or_else(|| panic!("There are only {} pairs.", data.len()));
+
+        println!("== Pair {number} ==");
+        print!("{}", explain(left, right).1);
+        return;
+    }
+
+    let data = parse_input(input).unwrap_or_else(|error| panic!("Failed to parse input. {error}"));
+
+    // `--bench [runs]` compares the two ways of solving part two.
+    if let Some(index) = args.iter().position(|a| a == "--bench") {
+        let runs = args
+            .get(index + 1)
+            .and_then(|a| a.parse().ok())
+            .unwrap_or(100);
+        bench(&data, runs);
+        return;
+    }
 
     let part_one = correctly_ordered_index_sum(&data);
     let part_two = divider_packets_index_product(&data);
 
-    println!("Correctly Ordered Pairs' Index Sum: {}", part_one);
+    println!("Correctly Ordered Pairs' Index Sum: {}", part_one.index_sum);
+
+    if !part_one.equal.is_empty() {
+        println!("Pairs With Equal Packets: {:?}", part_one.equal);
+    }
+
     println!("Sorted Packets Divider Index Product: {}", part_two);
 }
@@ -3,7 +3,9 @@
 const ZERO: Vector2<i32> = vec2(0, 0);
 
 use cgmath::{vec2, Vector2};
+use engine::{Ruleset, World};
 use parser::parse;
+use std::collections::HashMap;
 
 mod parser {
     use cgmath::{vec2, Vector2};
@@ -27,17 +29,33 @@ mod parser {
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
 enum Block {
     Rock,
     Sand,
+    Water,
+    Gas,
 }
 
-fn build_map(data: Vec<Vec<Vector2<i32>>>) -> Vec<(Vector2<i32>, Block)> {
-    let mut map = vec![];
+impl Block {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rock" => Some(Block::Rock),
+            "sand" => Some(Block::Sand),
+            "water" => Some(Block::Water),
+            "gas" => Some(Block::Gas),
+            _ => None,
+        }
+    }
+}
+
+type Map = HashMap<Vector2<i32>, Block>;
+
+fn build_map(data: Vec<Vec<Vector2<i32>>>) -> Map {
+    let mut map = HashMap::new();
 
     for path in data {
-        let mut previous = path[0].clone();
+        let mut previous = path[0];
 
         for point in path {
             let mut diff = point - previous;
@@ -51,7 +69,7 @@ fn build_map(data: Vec<Vec<Vector2<i32>>>) -> Vec<(Vector2<i32>, Block)> {
                 }
 
                 let step = point - diff;
-                map.push((step, Block::Rock));
+                map.insert(step, Block::Rock);
 
                 if diff == ZERO {
                     break;
@@ -65,75 +83,304 @@ fn build_map(data: Vec<Vec<Vector2<i32>>>) -> Vec<(Vector2<i32>, Block)> {
     map
 }
 
-fn total_sand(map: &Vec<(Vector2<i32>, Block)>) -> usize {
-    map.iter().filter(|e| e.1 == Block::Sand).count()
-}
+mod engine {
+    use crate::{min_max, Block, Map};
+    use cgmath::{vec2, Vector2};
+    use std::collections::HashMap;
+
+    /// The moves each material attempts, in order of priority. A particle takes
+    /// the first move onto a free cell and comes to rest when none are left.
+    /// Materials without any moves (rock) never leave their cell.
+    #[derive(Clone, Debug)]
+    pub(crate) struct Ruleset {
+        moves: HashMap<Block, Vec<Vector2<i32>>>,
+    }
 
-fn escapes(pos: &Vector2<i32>) -> [Vector2<i32>; 3] {
-    [pos + vec2(0, 1), pos + vec2(-1, 1), pos + vec2(1, 1)]
-}
+    impl Ruleset {
+        /// The puzzle's rules: sand falls down, then down-left, then down-right.
+        pub(crate) fn puzzle() -> Self {
+            let mut rules = Ruleset {
+                moves: HashMap::new(),
+            };
+            rules.set(Block::Sand, vec![vec2(0, 1), vec2(-1, 1), vec2(1, 1)]);
+            rules
+        }
+
+        /// The puzzle's rules plus water, which spreads sideways once it can't
+        /// fall, and gas, which rises the way sand falls.
+        pub(crate) fn fluids() -> Self {
+            let mut rules = Self::puzzle();
+            rules.set(
+                Block::Water,
+                vec![vec2(0, 1), vec2(-1, 1), vec2(1, 1), vec2(-1, 0), vec2(1, 0)],
+            );
+            rules.set(Block::Gas, vec![vec2(0, -1), vec2(-1, -1), vec2(1, -1)]);
+            rules
+        }
 
-fn first_valid_escape(map: &Vec<(Vector2<i32>, Block)>, pos: Vector2<i32>) -> Option<Vector2<i32>> {
-    for escape in escapes(&pos) {
-        // Check whether a block invalidates this escape.
-        if map.iter().find(|p| p.0 == escape).is_none() {
-            return Some(escape);
+        pub(crate) fn set(&mut self, block: Block, moves: Vec<Vector2<i32>>) {
+            self.moves.insert(block, moves);
+        }
+
+        pub(crate) fn moves(&self, block: Block) -> &[Vector2<i32>] {
+            self.moves.get(&block).map_or(&[], |m| m.as_slice())
         }
     }
 
-    None
-}
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub(crate) enum Outcome {
+        Rested(Vector2<i32>),
+        Escaped,
+        Blocked,
+    }
 
-fn part_one(map: &mut Vec<(Vector2<i32>, Block)>, source: &Vector2<i32>) -> u32 {
-    let (_, max) = min_max(&map);
+    struct Source {
+        position: Vector2<i32>,
+        material: Block,
+        active: bool,
+        // The route the last particle took, minus the cells it can no longer
+        // reach the same way. The next particle resumes from its end instead
+        // of walking down from the source again.
+        path: Vec<Vector2<i32>>,
+    }
 
-    loop {
-        let mut sand_pos = source.clone();
-        let mut rested = false;
+    pub(crate) struct World {
+        pub(crate) map: Map,
+        rules: Ruleset,
+        sources: Vec<Source>,
+        floor: Option<i32>,
+        min: Vector2<i32>,
+        max: Vector2<i32>,
+    }
 
-        while !rested {
-            if let Some(escape) = first_valid_escape(&map, sand_pos) {
-                // Capture end state.
-                if escape.y > max.y {
-                    return total_sand(&map) as u32;
-                }
+    impl World {
+        pub(crate) fn new(map: Map, rules: Ruleset) -> Self {
+            let (min, max) = if map.is_empty() {
+                (vec2(0, 0), vec2(0, 0))
+            } else {
+                min_max(&map)
+            };
+
+            World {
+                map,
+                rules,
+                sources: vec![],
+                floor: None,
+                min,
+                max,
+            }
+        }
 
-                sand_pos = escape;
-                continue;
+        /// Adds an infinite solid floor along the row `y`.
+        pub(crate) fn with_floor(mut self, y: i32) -> Self {
+            self.floor = Some(y);
+            self.max.y = self.max.y.max(y);
+            self.sources.iter().for_each(|s| {
+                self.min.x = self.min.x.min(s.position.x - (y - s.position.y));
+                self.max.x = self.max.x.max(s.position.x + (y - s.position.y));
+            });
+            self
+        }
+
+        pub(crate) fn with_source(mut self, position: Vector2<i32>, material: Block) -> Self {
+            self.min.x = self.min.x.min(position.x);
+            self.min.y = self.min.y.min(position.y);
+            self.max.x = self.max.x.max(position.x);
+            self.max.y = self.max.y.max(position.y);
+
+            if let Some(y) = self.floor {
+                self.min.x = self.min.x.min(position.x - (y - position.y));
+                self.max.x = self.max.x.max(position.x + (y - position.y));
             }
 
-            rested = true;
-            map.push((sand_pos, Block::Sand));
+            self.sources.push(Source {
+                position,
+                material,
+                active: true,
+                path: vec![],
+            });
+            self
         }
-    }
-}
 
-fn part_two(map: &mut Vec<(Vector2<i32>, Block)>, source: &Vector2<i32>) -> u32 {
-    let (_, max) = min_max(&map);
+        fn occupied(&self, pos: Vector2<i32>) -> bool {
+            self.map.contains_key(&pos) || self.floor.is_some_and(|y| pos.y >= y)
+        }
+
+        fn escaped(&self, pos: Vector2<i32>) -> bool {
+            pos.x < self.min.x || pos.y < self.min.y || pos.x > self.max.x || pos.y > self.max.y
+        }
+
+        /// Drops a single particle from the source at `index` and follows it
+        /// until it rests or leaves the world.
+        pub(crate) fn pour(&mut self, index: usize) -> Outcome {
+            let source = &self.sources[index];
+            let material = source.material;
+
+            if self.occupied(source.position) {
+                return Outcome::Blocked;
+            }
 
-    loop {
-        let mut sand_pos = source.clone();
-        let mut rested = false;
+            let mut path = std::mem::take(&mut self.sources[index].path);
 
-        while !rested {
-            if let Some(escape) = first_valid_escape(&map, sand_pos) {
-                if escape.y != max.y + 2 {
-                    sand_pos = escape;
-                    continue;
+            if path.is_empty() {
+                path.push(self.sources[index].position);
+            }
+
+            loop {
+                let pos = *path.last().unwrap();
+                let next = self
+                    .rules
+                    .moves(material)
+                    .iter()
+                    .map(|m| pos + m)
+                    // Never doubling back keeps sideways flow from oscillating.
+                    .find(|p| !self.occupied(*p) && !path.contains(p));
+
+                match next {
+                    Some(next) if self.escaped(next) => {
+                        self.sources[index].path = path;
+                        return Outcome::Escaped;
+                    }
+                    Some(next) => path.push(next),
+                    None => {
+                        self.sources[index].path = path;
+                        self.settle(pos, material);
+                        return Outcome::Rested(pos);
+                    }
                 }
             }
+        }
 
-            map.push((sand_pos, Block::Sand));
+        fn settle(&mut self, pos: Vector2<i32>, material: Block) {
+            self.map.insert(pos, material);
+
+            // A new particle only changes the decisions made on cells that
+            // could have moved into it, so every cached path stays valid up
+            // to the first such cell.
+            for source in self.sources.iter_mut() {
+                let moves = self.rules.moves(source.material);
+                let affected = source
+                    .path
+                    .iter()
+                    .position(|p| *p == pos || moves.iter().any(|m| p + m == pos));
+
+                if let Some(index) = affected {
+                    let keep = if source.path[index] == pos {
+                        index
+                    } else {
+                        index + 1
+                    };
+                    source.path.truncate(keep);
+                }
+            }
+        }
 
-            if sand_pos == *source {
-                return total_sand(&map) as u32;
+        /// Pours from every source in turn until each one is either blocked
+        /// or losing its particles out of the world.
+        pub(crate) fn run(&mut self) {
+            while self.sources.iter().any(|s| s.active) {
+                for index in 0..self.sources.len() {
+                    if !self.sources[index].active {
+                        continue;
+                    }
+
+                    match self.pour(index) {
+                        Outcome::Rested(_) => {}
+                        Outcome::Escaped | Outcome::Blocked => self.sources[index].active = false,
+                    }
+                }
             }
+        }
 
-            rested = true;
+        pub(crate) fn count(&self, block: Block) -> usize {
+            self.map.values().filter(|b| **b == block).count()
         }
     }
 }
 
+fn settle(
+    map: &mut Map,
+    sources: &[(Vector2<i32>, Block)],
+    rules: Ruleset,
+    floor: Option<i32>,
+) -> usize {
+    let mut world = World::new(std::mem::take(map), rules);
+
+    if let Some(y) = floor {
+        world = world.with_floor(y);
+    }
+
+    for (position, material) in sources {
+        world = world.with_source(*position, *material);
+    }
+
+    world.run();
+
+    let total = sources
+        .iter()
+        .map(|s| s.1)
+        .fold(vec![], |mut unique, m| {
+            if !unique.contains(&m) {
+                unique.push(m);
+            }
+            unique
+        })
+        .into_iter()
+        .map(|m| world.count(m))
+        .sum();
+
+    *map = world.map;
+    total
+}
+
+fn part_one(map: &mut Map, source: &Vector2<i32>) -> u32 {
+    settle(map, &[(*source, Block::Sand)], Ruleset::puzzle(), None) as u32
+}
+
+fn part_two(map: &mut Map, source: &Vector2<i32>) -> u32 {
+    let (_, max) = min_max(map);
+    settle(
+        map,
+        &[(*source, Block::Sand)],
+        Ruleset::puzzle(),
+        Some(max.y + 2),
+    ) as u32
+}
+
+// Reads the `--source x,y` and `--material name` flags used to try out
+// variants of the puzzle. Returns nothing when neither is given.
+fn parse_sources() -> Option<Vec<(Vector2<i32>, Block)>> {
+    let args = std::env::args().collect::<Vec<_>>();
+    let value = |flag: &str| {
+        args.iter()
+            .position(|a| a == flag)
+            .and_then(|i| args.get(i + 1))
+            .map(|s| s.as_str())
+    };
+
+    let material =
+        value("--material").map(|name| Block::from_name(name).expect("Unknown material."));
+
+    let mut sources = args
+        .windows(2)
+        .filter(|w| w[0] == "--source")
+        .map(|w| {
+            let (x, y) = w[1].split_once(',').expect("Sources are written as x,y.");
+            let position = vec2(
+                x.parse().expect("Invalid source x."),
+                y.parse().expect("Invalid source y."),
+            );
+            (position, material.unwrap_or(Block::Sand))
+        })
+        .collect::<Vec<_>>();
+
+    if sources.is_empty() {
+        sources.push((vec2(500, 0), material?));
+    }
+
+    Some(sources)
+}
+
 fn main() {
     if !std::env::args().any(|a| a == "--noimage") {
         println!("Image mode enabled.");
@@ -144,22 +391,28 @@ fn main() {
     let input = include_str!("input.txt");
     let data = parse(input).expect("Failed to parse input.").1;
     let map = build_map(data);
+    let (_, max) = min_max(&map);
 
     let mut map_one = map.clone();
-    let mut map_two = map.clone();
-    std::mem::drop(map);
+    let mut map_two = map;
 
     let source = vec2(500, 0);
 
-    let ans_one = part_one(&mut map_one, &source);
-    println!("Part One: {}", ans_one);
+    if let Some(sources) = parse_sources() {
+        println!("Running a variant with {} source(s).", sources.len());
 
-    println!(
-        "Working on part two... It takes longer, but will finish within about a minute or two."
-    );
+        let ans_one = settle(&mut map_one, &sources, Ruleset::fluids(), None);
+        println!("Part One: {}", ans_one);
 
-    let ans_two = part_two(&mut map_two, &source);
-    println!("Part Two: {}", ans_two);
+        let ans_two = settle(&mut map_two, &sources, Ruleset::fluids(), Some(max.y + 2));
+        println!("Part Two: {}", ans_two);
+    } else {
+        let ans_one = part_one(&mut map_one, &source);
+        println!("Part One: {}", ans_one);
+
+        let ans_two = part_two(&mut map_two, &source);
+        println!("Part Two: {}", ans_two);
+    }
 
     if !std::env::args().any(|a| a == "--noimage") {
         let desktop = dirs::desktop_dir().unwrap();
@@ -181,7 +434,11 @@ fn main() {
 
 #[cfg(test)]
 mod tests {
-    use crate::{build_map, parse, part_one, part_two};
+    use crate::{
+        build_map,
+        engine::{Outcome, Ruleset, World},
+        parse, part_one, part_two, settle, Block, Map,
+    };
     use cgmath::{vec2, Vector2};
 
     #[test]
@@ -258,16 +515,89 @@ mod tests {
         let result = part_two(&mut map, &sand_source);
         assert_eq!(result, 93);
     }
+
+    // A cup five cells wide on the inside and three deep.
+    fn cup() -> Map {
+        build_map(vec![vec![vec2(0, 2), vec2(0, 5), vec2(6, 5), vec2(6, 2)]])
+    }
+
+    #[test]
+    fn water_fills_a_cup() {
+        let mut map = cup();
+        let result = settle(
+            &mut map,
+            &[(vec2(3, 0), Block::Water)],
+            Ruleset::fluids(),
+            None,
+        );
+
+        // Sand would pile into a pyramid and spill, water levels out instead.
+        assert_eq!(result, 15);
+        assert!((1..=5).all(|x| (2..=4).all(|y| map.get(&vec2(x, y)) == Some(&Block::Water))));
+    }
+
+    #[test]
+    fn multiple_sources() {
+        // A cup three cells wide and two deep, sand poured in on the left and
+        // water on the right, one particle from each in turn.
+        let map = build_map(vec![vec![vec2(0, 2), vec2(0, 4), vec2(4, 4), vec2(4, 2)]]);
+        let mut world = World::new(map, Ruleset::fluids())
+            .with_source(vec2(1, 0), Block::Sand)
+            .with_source(vec2(3, 0), Block::Water);
+
+        let mut outcomes = [vec![], vec![]];
+        while outcomes
+            .iter()
+            .any(|o| !matches!(o.last(), Some(Outcome::Escaped)))
+        {
+            for (index, outcome) in outcomes.iter_mut().enumerate() {
+                if !matches!(outcome.last(), Some(Outcome::Escaped)) {
+                    outcome.push(world.pour(index));
+                }
+            }
+        }
+
+        // Water spreads along the bottom under the sand, sand climbs the left
+        // wall, and both spill over the rim with their fifth particle.
+        let rested = |cells: &[(i32, i32)]| {
+            let mut outcome = cells
+                .iter()
+                .map(|&(x, y)| Outcome::Rested(vec2(x, y)))
+                .collect::<Vec<_>>();
+            outcome.push(Outcome::Escaped);
+            outcome
+        };
+        assert_eq!(outcomes[0], rested(&[(1, 3), (1, 2), (2, 2), (1, 1)]));
+        assert_eq!(outcomes[1], rested(&[(2, 3), (3, 3), (3, 2), (2, 1)]));
+        assert_eq!(
+            (world.count(Block::Sand), world.count(Block::Water)),
+            (4, 4)
+        );
+    }
+
+    #[test]
+    fn gas_rises() {
+        // Gas rises into an upside-down cup and collects against its base.
+        let mut map = build_map(vec![vec![vec2(0, 3), vec2(0, 0), vec2(6, 0), vec2(6, 3)]]);
+        let result = settle(
+            &mut map,
+            &[(vec2(3, 3), Block::Gas)],
+            Ruleset::fluids(),
+            None,
+        );
+        assert_eq!(map.get(&vec2(3, 1)), Some(&Block::Gas));
+        assert_eq!(result, 9);
+    }
 }
 
-fn min_max(map: &Vec<(Vector2<i32>, Block)>) -> (Vector2<i32>, Vector2<i32>) {
-    let mut min = map
-        .first()
-        .expect("The map must contain at least one value to minmax it.")
-        .0;
-    let mut max = min.clone();
+fn min_max(map: &Map) -> (Vector2<i32>, Vector2<i32>) {
+    let mut min = *map
+        .keys()
+        .next()
+        .expect("The map must contain at least one value to minmax it.");
+    let mut max = min;
 
-    for pos in map.iter().map(|p| p.0) {
+    for pos in map.keys() {
         if pos.x > max.x {
             max.x = pos.x;
         }
@@ -289,8 +619,8 @@ fn min_max(map: &Vec<(Vector2<i32>, Block)>) -> (Vector2<i32>, Vector2<i32>) {
 }
 
 mod graphic {
-    use crate::{min_max, Block};
-    use cgmath::{vec2, Vector2};
+    use crate::{min_max, Block, Map};
+    use cgmath::vec2;
     use image::{
         imageops::{resize, FilterType},
         Rgb, RgbImage,
@@ -300,11 +630,11 @@ mod graphic {
     const IMAGE_SCALE: u32 = 8;
     const IMAGE_PADDING: i32 = 3;
 
-    pub(crate) fn snapshot(map: &Vec<(Vector2<i32>, Block)>) -> RgbImage {
-        let (mut min, mut max) = min_max(&map);
+    pub(crate) fn snapshot(map: &Map) -> RgbImage {
+        let (mut min, mut max) = min_max(map);
 
         max += vec2(IMAGE_PADDING, IMAGE_PADDING) + vec2(1, 1);
-        min += vec2(-1 * IMAGE_PADDING, -1 * IMAGE_PADDING);
+        min += vec2(-IMAGE_PADDING, -IMAGE_PADDING);
 
         let size = max - min;
         let mut image = RgbImage::new(size.x as u32, size.y as u32);
@@ -312,12 +642,13 @@ mod graphic {
         for x in min.x..max.x {
             for y in min.y..max.y {
                 let point = vec2(x, y);
-                let block = map.iter().find(|v| v.0 == point).map(|p| &p.1);
 
-                let color: Rgb<u8> = match block {
+                let color: Rgb<u8> = match map.get(&point) {
                     Some(value) => match value {
                         Block::Rock => Rgb([53, 50, 56]),
                         Block::Sand => Rgb([190, 90, 56]),
+                        Block::Water => Rgb([56, 110, 190]),
+                        Block::Gas => Rgb([150, 190, 120]),
                     },
                     None => Rgb([193, 180, 174]),
                 };
@@ -337,13 +668,13 @@ mod graphic {
         )
     }
 
-    pub(crate) fn build_floor(map: &mut Vec<(Vector2<i32>, Block)>) {
-        let (min, max) = min_max(&map);
+    pub(crate) fn build_floor(map: &mut Map) {
+        let (min, max) = min_max(map);
         let y = max.y + 1;
 
         for x in (min.x - FLOOR_PADDING as i32)..=(max.x + FLOOR_PADDING as i32) {
             let pos = vec2(x, y);
-            map.push((pos, Block::Rock));
+            map.insert(pos, Block::Rock);
         }
     }
 }
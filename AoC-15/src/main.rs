@@ -1,8 +1,10 @@
 #![allow(non_snake_case)]
 
+use intervals::IntervalSet;
+use params::Params;
 use std::ops::RangeInclusive;
 
-use nalgebra::{vector, Vector2};
+use nalgebra::Vector2;
 use nom::bytes::complete::take_till;
 use nom::character::complete::{i64 as parse_i64, multispace1};
 use nom::character::is_digit;
@@ -31,8 +33,8 @@ fn parse_line(input: &str) -> IResult<&str, Pair> {
         parse_i64,
     ))(input)
     .map(|result| {
-        let sensor = Vector2::new(result.1.1, result.1.3);
-        let beacon = Vector2::new(result.1.5, result.1.7);
+        let sensor = Vector2::new(result.1 .1, result.1 .3);
+        let beacon = Vector2::new(result.1 .5, result.1 .7);
         (result.0, Pair { sensor, beacon })
     })
 }
@@ -41,8 +43,138 @@ fn parse(input: &str) -> IResult<&str, Vec<Pair>> {
     separated_list0(multispace1, parse_line)(input)
 }
 
+mod params {
+    use std::fmt::{Display, Formatter};
+
+    /// The bundled parameter sets, and the only place their values live.
+    const DEFAULTS: &str = include_str!("params.toml");
+
+    const KEYS: [&str; 3] = ["row", "limit", "multiplier"];
+
+    /// The values that differ between the example and the real puzzle.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub(crate) struct Params {
+        pub(crate) row: i64,
+        pub(crate) limit: i64,
+        pub(crate) multiplier: i64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub(crate) enum ParamsError {
+        Toml(String),
+        UnknownSet(String),
+        UnknownKey(String),
+        NotAnInteger(String),
+        Missing { set: String, key: &'static str },
+    }
+
+    impl Display for ParamsError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ParamsError::Toml(error) => write!(f, "Invalid parameter file. {error}"),
+                ParamsError::UnknownSet(set) => write!(f, "There is no parameter set \"{set}\"."),
+                ParamsError::UnknownKey(key) => write!(f, "Unknown parameter \"{key}\"."),
+                ParamsError::NotAnInteger(key) => {
+                    write!(f, "Parameter \"{key}\" must be an integer.")
+                }
+                ParamsError::Missing { set, key } => {
+                    write!(f, "Parameter set \"{set}\" doesn't give \"{key}\".")
+                }
+            }
+        }
+    }
+
+    impl Params {
+        fn set(&mut self, key: &str, value: i64) -> Result<(), ParamsError> {
+            match key {
+                "row" => self.row = value,
+                "limit" => self.limit = value,
+                "multiplier" => self.multiplier = value,
+                _ => return Err(ParamsError::UnknownKey(key.to_string())),
+            }
+
+            Ok(())
+        }
+
+        /// Reads the named set (`example` or `real`) from the bundled
+        /// params.toml, with any values in `overrides` (another file of the
+        /// same shape) taking precedence. The overrides may also add sets of
+        /// their own.
+        pub(crate) fn load(set: &str, overrides: Option<&str>) -> Result<Self, ParamsError> {
+            let mut params = Params {
+                row: 0,
+                limit: 0,
+                multiplier: 0,
+            };
+            let mut given = vec![];
+
+            for text in [Some(DEFAULTS), overrides].into_iter().flatten() {
+                let config = text
+                    .parse::<toml::Value>()
+                    .map_err(|error| ParamsError::Toml(error.to_string()))?;
+
+                let Some(table) = config.get(set).and_then(|v| v.as_table()) else {
+                    continue;
+                };
+
+                for (key, value) in table {
+                    let value = value
+                        .as_integer()
+                        .ok_or_else(|| ParamsError::NotAnInteger(key.clone()))?;
+                    params.set(key, value)?;
+                    given.push(key.clone());
+                }
+            }
+
+            if given.is_empty() {
+                return Err(ParamsError::UnknownSet(set.to_string()));
+            }
+
+            match KEYS.into_iter().find(|key| !given.iter().any(|g| g == key)) {
+                Some(key) => Err(ParamsError::Missing {
+                    set: set.to_string(),
+                    key,
+                }),
+                None => Ok(params),
+            }
+        }
+
+        /// Applies any `--row`, `--limit` or `--multiplier` flags.
+        pub(crate) fn with_args(mut self, args: &[String]) -> Self {
+            for pair in args.windows(2) {
+                if let Some(key) = pair[0].strip_prefix("--") {
+                    if KEYS.contains(&key) {
+                        let value = pair[1].parse().expect("Parameters must be integers.");
+                        self.set(key, value).unwrap();
+                    }
+                }
+            }
+
+            self
+        }
+    }
+}
+
 fn main() {
-    let input = include_str!("input.txt");
+    let args = std::env::args().collect::<Vec<_>>();
+
+    let (input, set) = if args.iter().any(|a| a == "--example") {
+        (include_str!("test.txt"), "example")
+    } else {
+        (include_str!("input.txt"), "real")
+    };
+
+    // `--params <file>` overrides values from the bundled params.toml.
+    let overrides = args.iter().position(|a| a == "--params").map(|index| {
+        let path = args
+            .get(index + 1)
+            .expect("Expected a parameter file after --params.");
+        std::fs::read_to_string(path).expect("Failed to read parameter file.")
+    });
+
+    let params = Params::load(set, overrides.as_deref())
+        .unwrap_or_else(|error| panic!("{error}"))
+        .with_args(&args);
     let data = parse(input).expect("Failed to parse input.");
 
     assert!(
@@ -51,10 +183,33 @@ fn main() {
         data.0
     );
 
-    let one = part_one(&data.1, 2_000_000);
+    let one = part_one(&data.1, params.row);
     println!("Part One: {one}");
-    let two = part_two(&data.1, 4_000_000);
+    let two = if args.iter().any(|a| a == "--rotated") {
+        part_two_rotated(&data.1, params.limit, params.multiplier)
+    } else {
+        part_two(&data.1, params.limit, params.multiplier)
+    };
     println!("Part Two: {two}");
+
+    if !args.iter().any(|a| a == "--noimage") {
+        if let Some(desktop) = dirs::desktop_dir() {
+            // The rotated solver is near instant and finds the same beacon.
+            let distress = distress_beacon_rotated(&data.1, params.limit);
+
+            let image = graphic::coverage_map(&data.1, params.limit, distress);
+            let mut path = desktop.clone();
+            path.push("Day 15 Coverage.png");
+            image.save(path.clone()).expect("Failed to save image.");
+            println!("Saved coverage image to {}", path.to_str().unwrap());
+
+            let image = graphic::row_profile(&data.1, params.row);
+            let mut path = desktop;
+            path.push("Day 15 Row Profile.png");
+            image.save(path.clone()).expect("Failed to save image.");
+            println!("Saved row profile image to {}", path.to_str().unwrap());
+        }
+    }
 }
 
 // Order of a and b doesn't matter.
@@ -62,95 +217,368 @@ fn manhattan(a: &Vector2<i64>, b: &Vector2<i64>) -> i64 {
     (a.x - b.x).abs() + (a.y - b.y).abs()
 }
 
-fn domains_by_level(data: &[Pair]) -> Vec<(i64, RangeInclusive<i64>)> {
-    data.iter()
-        .map(|pair| (pair.sensor, manhattan(&pair.sensor, &pair.beacon)))
-        .flat_map(|(sensor, distance)| {
-            (-distance..=distance)
-                .map(|offset| {
-                    (
-                        sensor.y + offset,
-                        (sensor.x - distance + offset.abs())..=(sensor.x + distance - offset.abs()),
-                    )
-                })
-                .collect::<Vec<(i64, RangeInclusive<i64>)>>()
-        })
-        .collect::<Vec<_>>()
-}
+mod intervals {
+    use std::ops::RangeInclusive;
 
-fn part_one(data: &[Pair], row_index: i64) -> u32 {
-    let mut col_indexes = domains_by_level(data)
-        .into_iter()
-        .filter_map(|domain| {
-            if domain.0 == row_index {
-                Some(domain.1)
-            } else {
-                None
+    /// A set of integers held as sorted, disjoint and non-touching inclusive
+    /// ranges, so whole rows can be reasoned about without visiting each cell.
+    #[derive(Debug, Default, Clone, PartialEq, Eq)]
+    pub(crate) struct IntervalSet {
+        ranges: Vec<RangeInclusive<i64>>,
+    }
+
+    impl IntervalSet {
+        pub(crate) fn ranges(&self) -> &[RangeInclusive<i64>] {
+            &self.ranges
+        }
+
+        pub(crate) fn insert(&mut self, range: RangeInclusive<i64>) {
+            if range.is_empty() {
+                return;
             }
-        })
-        .flatten()
-        .collect::<Vec<_>>();
 
-    let relevant_beacons = data
-        .iter()
-        .filter_map(|pair| {
-            if pair.beacon.y == row_index {
-                Some(pair.beacon.x)
-            } else {
-                None
+            let (mut start, mut end) = range.into_inner();
+
+            // Everything that overlaps or touches the new range is absorbed into it.
+            let first = self.ranges.partition_point(|r| *r.end() < start - 1);
+            let last = self.ranges.partition_point(|r| *r.start() <= end + 1);
+
+            if first < last {
+                start = start.min(*self.ranges[first].start());
+                end = end.max(*self.ranges[last - 1].end());
             }
-        })
-        .collect::<Vec<_>>();
 
-    // Unique
-    col_indexes.sort();
-    col_indexes.dedup();
+            self.ranges.splice(first..last, [start..=end]);
+        }
 
-    col_indexes = col_indexes
-        .into_iter()
-        .filter(|index| !relevant_beacons.contains(index))
-        .collect::<Vec<_>>();
+        pub(crate) fn remove(&mut self, point: i64) {
+            let index = self.ranges.partition_point(|r| *r.end() < point);
+
+            match self.ranges.get(index) {
+                Some(range) if range.contains(&point) => {
+                    let (start, end) = range.clone().into_inner();
+                    let split = [start..=point - 1, point + 1..=end]
+                        .into_iter()
+                        .filter(|r| !r.is_empty());
+                    self.ranges.splice(index..=index, split);
+                }
+                _ => {}
+            }
+        }
+
+        /// The number of integers in the set.
+        pub(crate) fn len(&self) -> i64 {
+            self.ranges.iter().map(|r| r.end() - r.start() + 1).sum()
+        }
+
+        /// The ranges inside `bounds` that aren't part of the set.
+        pub(crate) fn gaps(&self, bounds: RangeInclusive<i64>) -> Vec<RangeInclusive<i64>> {
+            let (mut next, end) = bounds.into_inner();
+            let mut gaps = vec![];
+
+            for range in self.ranges.iter() {
+                if *range.start() > end {
+                    break;
+                }
+
+                if *range.start() > next {
+                    gaps.push(next..=*range.start() - 1);
+                }
+
+                next = next.max(*range.end() + 1);
+            }
+
+            if next <= end {
+                gaps.push(next..=end);
+            }
+
+            gaps
+        }
+    }
+
+    impl FromIterator<RangeInclusive<i64>> for IntervalSet {
+        fn from_iter<T: IntoIterator<Item = RangeInclusive<i64>>>(iter: T) -> Self {
+            let mut sorted = iter
+                .into_iter()
+                .filter(|r| !r.is_empty())
+                .collect::<Vec<_>>();
+            sorted.sort_by_key(|r| *r.start());
+
+            // Sorted input only ever merges into the last range.
+            let mut set = IntervalSet::default();
+            sorted.into_iter().for_each(|range| set.insert(range));
+            set
+        }
+    }
+}
+
+// The columns a sensor covers on the given row, if it reaches it at all.
+fn coverage(pair: &Pair, row: i64) -> Option<RangeInclusive<i64>> {
+    let reach = manhattan(&pair.sensor, &pair.beacon) - (row - pair.sensor.y).abs();
+
+    if reach < 0 {
+        return None;
+    }
+
+    Some((pair.sensor.x - reach)..=(pair.sensor.x + reach))
+}
 
-    col_indexes.len() as u32
+fn row_coverage(data: &[Pair], row: i64) -> IntervalSet {
+    data.iter().filter_map(|pair| coverage(pair, row)).collect()
 }
 
-fn part_two(data: &[Pair], limit: i64) -> i64 {
-    let distances = data
+fn part_one(data: &[Pair], row_index: i64) -> u32 {
+    let mut covered = row_coverage(data, row_index);
+
+    for pair in data.iter().filter(|pair| pair.beacon.y == row_index) {
+        covered.remove(pair.beacon.x);
+    }
+
+    covered.len() as u32
+}
+
+fn distress_beacon(data: &[Pair], limit: i64) -> Vector2<i64> {
+    // Each row is a handful of ranges, so the only cells ever looked at
+    // are the ones in the gaps between them.
+    let (x, y) = (0..=limit)
+        .find_map(|y| {
+            row_coverage(data, y)
+                .gaps(0..=limit)
+                .first()
+                .map(|gap| (*gap.start(), y))
+        })
+        .expect("Failed to find answer for part two.");
+
+    Vector2::new(x, y)
+}
+
+// Rotating by 45° turns every diamond into an axis-aligned square, with
+// u = x + y and v = x - y. The only uncovered cell must sit just outside the
+// edge of some sensor's square, so it lies where two perimeter lines cross,
+// where a perimeter line crosses the border of the search area, or in one of
+// its corners. That yields a few thousand candidates to check rather than
+// millions of rows.
+fn distress_beacon_rotated(data: &[Pair], limit: i64) -> Vector2<i64> {
+    let sensors = data
         .iter()
         .map(|pair| (pair.sensor, manhattan(&pair.sensor, &pair.beacon)))
         .collect::<Vec<_>>();
 
-    let answer = domains_by_level(data)
-        .into_iter()
-        // Instead of iterating over all the trillions of values in the input,
-        // we instead just iterate over the couple million which surround the
-        // sensor-beacon ranges, avoiding checking a huge number of frivilous
-        // coordinates.
-        .flat_map(|(y, range)| {
-            if range.end() == range.start() {
-                // This technique is inneficient, as it takes the positions above and below
-                // for capping points to the diamond shapes. However, it's functional
-                // and as of writing, I'm getting the answer in good time. So I'll leave it here
-                // for now.
-                [(*range.start(), y - 1), (*range.start(), y + 1)]
-            } else {
-                [(*range.start() - 1, y), (*range.end() + 1, y)]
-            }
-        })
+    let mut u_lines = vec![];
+    let mut v_lines = vec![];
+
+    for (sensor, distance) in sensors.iter() {
+        let (u, v) = (sensor.x + sensor.y, sensor.x - sensor.y);
+        u_lines.extend([u - distance - 1, u + distance + 1]);
+        v_lines.extend([v - distance - 1, v + distance + 1]);
+    }
+
+    u_lines.sort();
+    u_lines.dedup();
+    v_lines.sort();
+    v_lines.dedup();
+
+    let corners = [(0, 0), (0, limit), (limit, 0), (limit, limit)];
+
+    // Where each line meets x = 0, x = limit, y = 0 and y = limit.
+    let borders = u_lines
+        .iter()
+        .flat_map(|u| [(0, *u), (limit, u - limit), (*u, 0), (u - limit, limit)])
+        .chain(
+            v_lines
+                .iter()
+                .flat_map(|v| [(0, -v), (limit, limit - v), (*v, 0), (v + limit, limit)]),
+        );
+
+    let (x, y) = u_lines
+        .iter()
+        .flat_map(|u| v_lines.iter().map(move |v| (u, v)))
+        // Points with mismatched parity fall between cells.
+        .filter(|(u, v)| (*u - *v) % 2 == 0)
+        .map(|(u, v)| ((u + v) / 2, (u - v) / 2))
+        .chain(borders)
+        .chain(corners)
         .filter(|(x, y)| (0..=limit).contains(x) && (0..=limit).contains(y))
         .find(|(x, y)| {
-            !distances
+            !sensors
                 .iter()
-                .any(|(sensor, distance)| manhattan(sensor, &vector![*x, *y]) <= *distance)
+                .any(|(sensor, distance)| manhattan(sensor, &Vector2::new(*x, *y)) <= *distance)
         })
         .expect("Failed to find answer for part two.");
 
-    return answer.0 * 4_000_000 + answer.1;
+    Vector2::new(x, y)
+}
+
+fn tuning_frequency(beacon: Vector2<i64>, multiplier: i64) -> i64 {
+    beacon.x * multiplier + beacon.y
+}
+
+fn part_two(data: &[Pair], limit: i64, multiplier: i64) -> i64 {
+    tuning_frequency(distress_beacon(data, limit), multiplier)
+}
+
+fn part_two_rotated(data: &[Pair], limit: i64, multiplier: i64) -> i64 {
+    tuning_frequency(distress_beacon_rotated(data, limit), multiplier)
+}
+
+mod graphic {
+    use crate::{coverage, manhattan, row_coverage, Pair};
+    use image::{Rgb, RgbImage};
+    use nalgebra::Vector2;
+
+    const MAP_SIZE: u32 = 800;
+    const PROFILE_WIDTH: u32 = 800;
+    const LANE_HEIGHT: u32 = 8;
+    const MARKER_RADIUS: i64 = 3;
+
+    const BACKGROUND: Rgb<u8> = Rgb([24, 26, 33]);
+    const OUTLINE: Rgb<u8> = Rgb([230, 230, 230]);
+    const SENSOR: Rgb<u8> = Rgb([90, 170, 250]);
+    const BEACON: Rgb<u8> = Rgb([250, 200, 60]);
+    const DISTRESS: Rgb<u8> = Rgb([250, 60, 60]);
+
+    // Deeper blues where more sensors overlap.
+    fn shade(overlaps: usize) -> Rgb<u8> {
+        if overlaps == 0 {
+            return BACKGROUND;
+        }
+
+        let depth = (overlaps.min(6) * 20) as u8;
+        Rgb([40, 60 + depth / 2, 90 + depth])
+    }
+
+    fn draw_marker(image: &mut RgbImage, center: (i64, i64), radius: i64, color: Rgb<u8>) {
+        for x in (center.0 - radius)..=(center.0 + radius) {
+            for y in (center.1 - radius)..=(center.1 + radius) {
+                if (0..image.width() as i64).contains(&x) && (0..image.height() as i64).contains(&y)
+                {
+                    image.put_pixel(x as u32, y as u32, color);
+                }
+            }
+        }
+    }
+
+    /// Draws the search area `0..=limit` on both axes, scaled down to a fixed
+    /// size, with every sensor's diamond, the beacons, and the distress beacon.
+    pub(crate) fn coverage_map(data: &[Pair], limit: i64, distress: Vector2<i64>) -> RgbImage {
+        // World units per pixel.
+        let scale = (limit + 1) as f64 / MAP_SIZE as f64;
+        let to_world = |pixel: u32| ((pixel as f64 + 0.5) * scale) as i64;
+        let to_pixel = |point: &Vector2<i64>| {
+            (
+                (point.x as f64 / scale) as i64,
+                (point.y as f64 / scale) as i64,
+            )
+        };
+
+        let sensors = data
+            .iter()
+            .map(|pair| (pair.sensor, manhattan(&pair.sensor, &pair.beacon)))
+            .collect::<Vec<_>>();
+
+        let mut image = RgbImage::new(MAP_SIZE, MAP_SIZE);
+
+        for (px, py, pixel) in image.enumerate_pixels_mut() {
+            let point = Vector2::new(to_world(px), to_world(py));
+            let distances = sensors
+                .iter()
+                .map(|(sensor, distance)| (manhattan(sensor, &point), *distance));
+
+            let mut overlaps = 0;
+            let mut edge = false;
+
+            for (to_point, distance) in distances {
+                if to_point <= distance {
+                    overlaps += 1;
+                }
+
+                // Anything within a pixel of the boundary is drawn as outline.
+                if ((to_point - distance).abs() as f64) < scale {
+                    edge = true;
+                }
+            }
+
+            *pixel = if edge { OUTLINE } else { shade(overlaps) };
+        }
+
+        for pair in data {
+            draw_marker(&mut image, to_pixel(&pair.sensor), MARKER_RADIUS, SENSOR);
+            draw_marker(&mut image, to_pixel(&pair.beacon), MARKER_RADIUS, BEACON);
+        }
+
+        // A larger ring so the distress beacon stands out from the rest.
+        let center = to_pixel(&distress);
+        draw_marker(&mut image, center, MARKER_RADIUS * 3, DISTRESS);
+        draw_marker(&mut image, center, MARKER_RADIUS * 2, BACKGROUND);
+        draw_marker(&mut image, center, MARKER_RADIUS, DISTRESS);
+
+        image
+    }
+
+    /// Draws the given row with one lane per sensor that reaches it, and the
+    /// union of all of them (the part one answer) along the bottom. Beacons
+    /// sitting on the row are marked across every lane.
+    pub(crate) fn row_profile(data: &[Pair], row: i64) -> RgbImage {
+        let lanes = data
+            .iter()
+            .filter_map(|pair| coverage(pair, row))
+            .collect::<Vec<_>>();
+        let union = row_coverage(data, row);
+
+        let (min, max) = match (union.ranges().first(), union.ranges().last()) {
+            (Some(first), Some(last)) => (*first.start(), *last.end()),
+            _ => (0, 1),
+        };
+        let padding = (max - min) / 20 + 1;
+        let (min, max) = (min - padding, max + padding);
+
+        let scale = (max - min + 1) as f64 / PROFILE_WIDTH as f64;
+        let to_pixel = |x: i64| ((x - min) as f64 / scale) as u32;
+
+        // Each sensor lane, then a spacer, then the union.
+        let height = (lanes.len() as u32 + 2) * LANE_HEIGHT;
+        let mut image = RgbImage::from_pixel(PROFILE_WIDTH, height, BACKGROUND);
+
+        let mut fill = |range: &std::ops::RangeInclusive<i64>, lane: u32, color: Rgb<u8>| {
+            let (start, end) = (to_pixel(*range.start()), to_pixel(*range.end()));
+
+            for x in start..=end.min(PROFILE_WIDTH - 1) {
+                for y in lane * LANE_HEIGHT + 1..(lane + 1) * LANE_HEIGHT - 1 {
+                    image.put_pixel(x, y, color);
+                }
+            }
+        };
+
+        for (lane, range) in lanes.iter().enumerate() {
+            fill(range, lane as u32, shade(1));
+        }
+
+        for range in union.ranges() {
+            fill(range, lanes.len() as u32 + 1, OUTLINE);
+        }
+
+        for pair in data.iter().filter(|pair| pair.beacon.y == row) {
+            let x = to_pixel(pair.beacon.x).min(PROFILE_WIDTH - 1);
+
+            for y in 0..height {
+                image.put_pixel(x, y, BEACON);
+            }
+        }
+
+        image
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{domains_by_level, parse, parse_line, part_one, part_two};
+    use crate::{
+        coverage, distress_beacon_rotated,
+        intervals::IntervalSet,
+        params::{Params, ParamsError},
+        parse, parse_line, part_one, part_two, part_two_rotated,
+    };
+    use nalgebra::Vector2;
+    use std::ops::RangeInclusive;
 
     #[test]
     fn part_one_works() {
@@ -166,10 +594,94 @@ mod tests {
         let input = include_str!("test.txt");
         let data = parse(input).unwrap();
         assert!(data.0.is_empty());
-        let result = part_two(&data.1, 20);
+        let result = part_two(&data.1, 20, 4_000_000);
         assert_eq!(result, 56000011);
     }
 
+    #[test]
+    fn rotated_solver_agrees() {
+        let input = include_str!("test.txt");
+        let data = parse(input).unwrap().1;
+        assert_eq!(
+            part_two_rotated(&data, 20, 4_000_000),
+            part_two(&data, 20, 4_000_000)
+        );
+
+        // A single sensor leaves the far corner of the area open.
+        let data = parse("Sensor at x=0, y=0: closest beacon is at x=5, y=6")
+            .unwrap()
+            .1;
+        assert_eq!(part_two_rotated(&data, 6, 7), part_two(&data, 6, 7));
+
+        // Small sensors boxing in a single cell on the left border.
+        let data = parse(
+            "Sensor at x=1, y=1: closest beacon is at x=2, y=1
+Sensor at x=1, y=3: closest beacon is at x=2, y=3
+Sensor at x=4, y=2: closest beacon is at x=1, y=2
+Sensor at x=0, y=0: closest beacon is at x=0, y=1
+Sensor at x=0, y=4: closest beacon is at x=0, y=3
+Sensor at x=2, y=0: closest beacon is at x=2, y=1
+Sensor at x=2, y=4: closest beacon is at x=2, y=3",
+        )
+        .unwrap()
+        .1;
+        assert_eq!(distress_beacon_rotated(&data, 4), Vector2::new(0, 2));
+        assert_eq!(part_two_rotated(&data, 4, 5), part_two(&data, 4, 5));
+    }
+
+    #[test]
+    fn params_work() {
+        let example = Params::load("example", None).unwrap();
+        assert_eq!(
+            example,
+            Params {
+                row: 10,
+                limit: 20,
+                multiplier: 4_000_000
+            }
+        );
+
+        let args = ["--limit", "30", "--multiplier", "10", "--rotated"].map(String::from);
+        let params = example.with_args(&args);
+        assert_eq!(params.row, 10);
+        assert_eq!(params.limit, 30);
+        assert_eq!(params.multiplier, 10);
+
+        let data = parse(include_str!("test.txt")).unwrap().1;
+        assert_eq!(part_one(&data, params.row), 26);
+        assert_eq!(part_two(&data, 20, params.multiplier), 151);
+    }
+
+    #[test]
+    fn params_file_overrides() {
+        let overrides = "[real]\nrow = 7\n\n[tiny]\nrow = 1\nlimit = 2\nmultiplier = 3";
+        let real = Params::load("real", Some(overrides)).unwrap();
+        assert_eq!((real.row, real.limit), (7, 4_000_000));
+
+        let tiny = Params::load("tiny", Some(overrides)).unwrap();
+        assert_eq!((tiny.row, tiny.limit, tiny.multiplier), (1, 2, 3));
+
+        assert_eq!(
+            Params::load("reel", None),
+            Err(ParamsError::UnknownSet("reel".to_string()))
+        );
+        assert_eq!(
+            Params::load("small", Some("[small]\nrow = 1")),
+            Err(ParamsError::Missing {
+                set: "small".to_string(),
+                key: "limit"
+            })
+        );
+        assert_eq!(
+            Params::load("real", Some("[real]\nrows = 1")),
+            Err(ParamsError::UnknownKey("rows".to_string()))
+        );
+        assert_eq!(
+            Params::load("real", Some("[real]\nrow = \"1\"")),
+            Err(ParamsError::NotAnInteger("row".to_string()))
+        );
+    }
+
     #[test]
     fn parse_line_works() {
         let input = "Sensor at x=-3729579, y=1453415: closest beacon is at x=4078883, y=2522671";
@@ -182,20 +694,22 @@ mod tests {
     }
 
     #[test]
-    fn domain_leveling_works() {
+    fn coverage_works() {
         let input_1 = "Sensor at x=15, y=5: closest beacon is at x=20, y=3";
         let result_1 = parse_line(input_1)
             .expect("Parsing straight-up failed for first input.")
             .1;
-        let mut domains_1 = domains_by_level(&vec![result_1]);
-        domains_1.sort_by_key(|domain| domain.0);
+        let domains_1 = (-3..=13)
+            .filter_map(|row| coverage(&result_1, row).map(|range| (row, range)))
+            .collect::<Vec<_>>();
 
         let input_2 = "Sensor at x=15, y=5: closest beacon is at x=10, y=3";
         let result_2 = parse_line(input_2)
             .expect("Parsing straight-up failed for second input.")
             .1;
-        let mut domains_2 = domains_by_level(&vec![result_2]);
-        domains_2.sort_by_key(|domain| domain.0);
+        let domains_2 = (-3..=13)
+            .filter_map(|row| coverage(&result_2, row).map(|range| (row, range)))
+            .collect::<Vec<_>>();
 
         let expected = [
             (-2, 15..=15),
@@ -218,4 +732,27 @@ mod tests {
         assert_eq!(domains_1, expected);
         assert_eq!(domains_2, expected);
     }
+
+    #[test]
+    fn interval_set_works() {
+        let mut set = [5..=8, 1..=2, 3..=4, 12..=14, 13..=20]
+            .into_iter()
+            .collect::<IntervalSet>();
+        let expect =
+            |ranges: &[RangeInclusive<i64>]| ranges.iter().cloned().collect::<IntervalSet>();
+        assert_eq!(set, expect(&[1..=8, 12..=20]));
+        assert_eq!(set.len(), 17);
+
+        set.insert(9..=10);
+        assert_eq!(set, expect(&[1..=10, 12..=20]));
+        set.insert(11..=11);
+        assert_eq!(set, expect(&[1..=20]));
+
+        set.remove(1);
+        set.remove(7);
+        set.remove(30);
+        assert_eq!(set, expect(&[2..=6, 8..=20]));
+        assert_eq!(set.gaps(0..=25), vec![0..=1, 7..=7, 21..=25]);
+        assert_eq!(set.gaps(10..=15), vec![]);
+    }
 }
@@ -1,89 +1,1307 @@
 #![allow(non_snake_case)]
 
-use indextree::Arena;
+use search::{best_disjoint_pair, Engine, Pruning, Stats};
+use std::collections::{HashMap, VecDeque};
+
+use std::fmt::Display;
+
 use nom::{
+    branch::alt,
     bytes::complete::tag,
-    bytes::complete::take,
-    character::complete::{newline, u32 as u32_parser},
-    multi::separated_list0,
+    character::complete::{alpha1, u32 as u32_parser},
+    multi::separated_list1,
     sequence::{preceded, tuple},
     IResult,
-    Parser,
 };
 
-#[derive(PartialEq, Debug)]
-struct Valve<'a> {
-    id: &'a str,
+/// A valve with its tunnels resolved to indexes into the graph.
+#[derive(PartialEq, Eq, Debug, Clone)]
+struct Valve {
+    id: String,
     flow_rate: u32,
-    connection_ids: Vec<&'a str>,
+    connections: Vec<usize>,
+}
+
+/// Every valve in the scan. IDs are interned, so valves refer to each other
+/// by their index in `valves`.
+#[derive(PartialEq, Eq, Debug, Default)]
+struct Graph {
+    valves: Vec<Valve>,
+    indexes: HashMap<String, usize>,
+}
+
+impl Graph {
+    fn intern(&mut self, id: &str) -> usize {
+        if let Some(index) = self.indexes.get(id) {
+            return *index;
+        }
+
+        let index = self.valves.len();
+        self.indexes.insert(id.to_string(), index);
+        self.valves.push(Valve {
+            id: id.to_string(),
+            flow_rate: 0,
+            connections: vec![],
+        });
+        index
+    }
+
+    fn index_of(&self, id: &str) -> Option<usize> {
+        self.indexes.get(id).copied()
+    }
+
+    // The tunnels as an adjacency list, indexed the same way as the valves.
+    fn adjacency(&self) -> Vec<Vec<usize>> {
+        self.valves.iter().map(|v| v.connections.clone()).collect()
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+enum ParseErrorKind {
+    /// The line doesn't describe a valve.
+    Syntax,
+    /// The line describes a valve but has more after it.
+    TrailingInput,
+    UndefinedValve(String),
+    DuplicateValve(String),
+}
+
+/// Where the scan went wrong, counting lines and columns from one.
+#[derive(PartialEq, Eq, Debug)]
+struct ParseError {
+    line: usize,
+    column: usize,
+    kind: ParseErrorKind,
 }
 
-fn parse_line(input: &str) -> IResult<&str, (&str, u32, Vec<&str>)> {
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Line {}, column {}: ", self.line, self.column)?;
+
+        match &self.kind {
+            ParseErrorKind::Syntax => write!(f, "expected a valve description"),
+            ParseErrorKind::TrailingInput => write!(f, "unexpected input after the tunnels"),
+            ParseErrorKind::UndefinedValve(id) => write!(f, "valve {id} is never defined"),
+            ParseErrorKind::DuplicateValve(id) => write!(f, "valve {id} is defined twice"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+type ValveInfo<'a> = (&'a str, u32, Vec<&'a str>);
+
+fn parse_line(input: &str) -> IResult<&str, ValveInfo<'_>> {
     tuple((
-        preceded(tag("Valve "), take(2u8)),
+        preceded(tag("Valve "), alpha1),
         preceded(tag(" has flow rate="), u32_parser),
         preceded(
-            tag("; tunnels lead to valves ").or(tag("; tunnel leads to valve ")),
-            separated_list0(tag(", "), take(2u8)),
+            alt((
+                tag("; tunnels lead to valves "),
+                tag("; tunnel leads to valve "),
+            )),
+            separated_list1(tag(", "), alpha1),
         ),
     ))(input)
 }
 
-fn parse(input: &str) -> IResult<&str, Vec<Valve>> {
-    separated_list0(
-        newline,
-        parse_line,
-    )(input)
-    .map(|(left, list)| {
-        (
-            left,
-            list.into_iter()
-                .map(|info| Valve {
-                    id: info.0,
-                    flow_rate: info.1,
-                    connection_ids: info.2,
+fn parse(input: &str) -> Result<Graph, ParseError> {
+    // The columns are found from where each slice sits within its line.
+    let column = |line: &str, part: &str| part.as_ptr() as usize - line.as_ptr() as usize + 1;
+
+    let mut graph = Graph::default();
+    let mut lines = vec![];
+
+    for (number, line) in input.lines().enumerate().map(|(i, l)| (i + 1, l)) {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (rest, (id, flow_rate, connection_ids)) = parse_line(line).map_err(|error| {
+            let column = match error {
+                nom::Err::Error(e) | nom::Err::Failure(e) => column(line, e.input),
+                nom::Err::Incomplete(_) => line.len() + 1,
+            };
+
+            ParseError {
+                line: number,
+                column,
+                kind: ParseErrorKind::Syntax,
+            }
+        })?;
+
+        if !rest.trim_end().is_empty() {
+            return Err(ParseError {
+                line: number,
+                column: column(line, rest),
+                kind: ParseErrorKind::TrailingInput,
+            });
+        }
+
+        if graph.index_of(id).is_some() {
+            return Err(ParseError {
+                line: number,
+                column: column(line, id),
+                kind: ParseErrorKind::DuplicateValve(id.to_string()),
+            });
+        }
+
+        let index = graph.intern(id);
+        graph.valves[index].flow_rate = flow_rate;
+        lines.push((number, line, index, connection_ids));
+    }
+
+    // Tunnels can lead to valves described further down, so they're only
+    // resolved once every valve has been seen.
+    for (number, line, index, connection_ids) in lines {
+        let connections = connection_ids
+            .into_iter()
+            .map(|connection| {
+                graph.index_of(connection).ok_or_else(|| ParseError {
+                    line: number,
+                    column: column(line, connection),
+                    kind: ParseErrorKind::UndefinedValve(connection.to_string()),
                 })
-                .collect(),
-        )
-    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        graph.valves[index].connections = connections;
+    }
+
+    Ok(graph)
+}
+
+// Every tunnel takes a minute, so a breadth-first search gives the travel
+// time from one valve to every other.
+fn travel_times(adjacency: &[Vec<usize>], from: usize) -> Vec<u32> {
+    let mut times = vec![u32::MAX; adjacency.len()];
+    let mut queue = VecDeque::from([from]);
+    times[from] = 0;
+
+    while let Some(current) = queue.pop_front() {
+        for next in adjacency[current].iter() {
+            if times[*next] == u32::MAX {
+                times[*next] = times[current] + 1;
+                queue.push_back(*next);
+            }
+        }
+    }
+
+    times
+}
+
+/// The tunnels reduced to the starting valve and the valves worth opening,
+/// with the travel time between each pair of them.
+struct Network {
+    ids: Vec<String>,
+    flow_rates: Vec<u32>,
+    distances: Vec<Vec<u32>>,
+    // The valves worth opening come first, so they can be tracked in a bitmask
+    // by index. The start comes after them unless it has a flow rate itself.
+    flowing: usize,
+    start: usize,
+    // The full set of tunnels is kept around to walk routes minute by minute.
+    tunnels: Vec<Vec<usize>>,
+    all_ids: Vec<String>,
+    // Where each kept valve sits in the full set.
+    indexes: Vec<usize>,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+enum NetworkError {
+    UnknownStart(String),
+    /// More valves worth opening than fit in the search's bitmask.
+    TooManyValves(usize),
+}
+
+impl Display for NetworkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkError::UnknownStart(id) => write!(f, "There is no valve {id} to start at."),
+            NetworkError::TooManyValves(count) => write!(
+                f,
+                "{count} valves have a flow rate, but at most {} can be searched.",
+                u64::BITS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+impl Network {
+    /// Valves that can't be reached from the start are left out, since they
+    /// can never be opened.
+    fn new(graph: &Graph, start: &str) -> Result<Self, NetworkError> {
+        let valves = &graph.valves;
+        let adjacency = graph.adjacency();
+        let start = graph
+            .index_of(start)
+            .ok_or_else(|| NetworkError::UnknownStart(start.to_string()))?;
+        let from_start = travel_times(&adjacency, start);
+
+        let mut kept = valves
+            .iter()
+            .enumerate()
+            .filter(|(index, valve)| valve.flow_rate > 0 && from_start[*index] != u32::MAX)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let flowing = kept.len();
+
+        if flowing > u64::BITS as usize {
+            return Err(NetworkError::TooManyValves(flowing));
+        }
+
+        if valves[start].flow_rate == 0 {
+            kept.push(start);
+        }
+
+        let distances = kept
+            .iter()
+            .map(|from| {
+                let times = travel_times(&adjacency, *from);
+                kept.iter().map(|to| times[*to]).collect()
+            })
+            .collect();
+
+        Ok(Network {
+            ids: kept.iter().map(|index| valves[*index].id.clone()).collect(),
+            flow_rates: kept.iter().map(|index| valves[*index].flow_rate).collect(),
+            distances,
+            flowing,
+            start: kept.iter().position(|index| *index == start).unwrap(),
+            tunnels: adjacency,
+            all_ids: valves.iter().map(|valve| valve.id.clone()).collect(),
+            indexes: kept,
+        })
+    }
+
+    /// The valves passed through (by index into the full set) when walking
+    /// between two kept valves, not including the first.
+    fn walk(&self, from: usize, to: usize) -> Vec<usize> {
+        let (from, to) = (self.indexes[from], self.indexes[to]);
+        let mut previous = vec![None; self.tunnels.len()];
+        let mut queue = VecDeque::from([from]);
+
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                break;
+            }
+
+            for next in self.tunnels[current].iter() {
+                if previous[*next].is_none() && *next != from {
+                    previous[*next] = Some(current);
+                    queue.push_back(*next);
+                }
+            }
+        }
+
+        // Follow the breadcrumbs back from the destination.
+        let mut steps = vec![];
+        let mut current = to;
+
+        while current != from {
+            steps.push(current);
+            current = previous[current].expect("The valves aren't connected.");
+        }
+
+        steps.reverse();
+        steps
+    }
+}
+
+mod search {
+    use crate::Network;
+    use std::collections::HashMap;
+
+    /// How the engine decides a branch isn't worth exploring.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub(crate) enum Pruning {
+        /// Only skip states already reached with at least as much pressure.
+        None,
+        /// Also skip branches that couldn't beat the best plan so far even if
+        /// every closed valve were walked to directly and opened.
+        Reachable,
+        /// Also skip branches that couldn't beat the best plan so far even if
+        /// the closed valves were opened largest first, one per shortest hop.
+        Sorted,
+    }
+
+    impl Pruning {
+        pub(crate) fn from_name(name: &str) -> Option<Self> {
+            match name {
+                "none" => Some(Pruning::None),
+                "reachable" => Some(Pruning::Reachable),
+                "sorted" => Some(Pruning::Sorted),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub(crate) struct Stats {
+        /// States that were expanded into their successors.
+        pub(crate) explored: usize,
+        /// States skipped because the same one was already reached with at
+        /// least as much pressure.
+        pub(crate) dominated: usize,
+        /// States skipped by the upper bound.
+        pub(crate) pruned: usize,
+    }
+
+    // Where an actor is, how many minutes it has left and which valves are
+    // open (as a bitmask over the network's indexes).
+    type State = (usize, u32, u64);
+
+    // What a branch has to be able to reach to be worth exploring.
+    #[derive(Clone, Copy)]
+    enum Goal {
+        /// More than the best plan found so far.
+        Best,
+        /// At least this much pressure.
+        AtLeast(u32),
+    }
+
+    pub(crate) struct Engine<'n> {
+        network: &'n Network,
+        pruning: Pruning,
+        // The most pressure any path has arrived at each state with.
+        memo: HashMap<State, u32>,
+        // The most pressure released by the end for each set of open valves.
+        best_by_opened: HashMap<u64, u32>,
+        best: u32,
+        shortest_hop: u32,
+        stats: Stats,
+        // The valves opened on the way to the current state, in order.
+        route: Vec<usize>,
+        best_route: Vec<usize>,
+        routes_by_opened: HashMap<u64, Vec<usize>>,
+    }
+
+    impl<'n> Engine<'n> {
+        pub(crate) fn new(network: &'n Network, pruning: Pruning) -> Self {
+            let shortest_hop = network
+                .distances
+                .iter()
+                .flatten()
+                .copied()
+                .filter(|d| *d > 0)
+                .min()
+                .unwrap_or(1);
+
+            Engine {
+                network,
+                pruning,
+                memo: HashMap::new(),
+                best_by_opened: HashMap::new(),
+                best: 0,
+                shortest_hop,
+                stats: Stats::default(),
+                route: vec![],
+                best_route: vec![],
+                routes_by_opened: HashMap::new(),
+            }
+        }
+
+        /// Counts for every search the engine has run, including the ones
+        /// `best_by_opened` makes to set its bound.
+        pub(crate) fn stats(&self) -> Stats {
+            self.stats
+        }
+
+        fn reset(&mut self) {
+            self.memo.clear();
+            self.best_by_opened.clear();
+            self.best = 0;
+            self.best_route.clear();
+            self.routes_by_opened.clear();
+        }
+
+        /// The order valves are opened in for the result of the last `best`.
+        pub(crate) fn best_route(&self) -> &[usize] {
+            &self.best_route
+        }
+
+        /// The order valves are opened in for a set from the last `best_by_opened`.
+        pub(crate) fn route_for(&self, opened: u64) -> &[usize] {
+            self.routes_by_opened
+                .get(&opened)
+                .map_or(&[], |r| r.as_slice())
+        }
+
+        /// The most pressure a single actor can release in the given time.
+        pub(crate) fn best(&mut self, minutes: u32) -> u32 {
+            self.reset();
+            self.visit((self.network.start, minutes, 0), 0, Goal::Best);
+            self.best
+        }
+
+        /// The most pressure a single actor can release in the given time for
+        /// every set of valves it could open, as far as the table is needed to
+        /// find the best pair of disjoint sets.
+        ///
+        /// Bounding against the best plan so far would be unsound here, since a
+        /// plan that loses overall can still be half of the best pair. Instead
+        /// the bound is held against a pair known to be possible: the best plan
+        /// plus the best plan for the valves it leaves closed. Every set in a
+        /// better pair is worth at least that second plan, so branches that
+        /// can't reach it are pruned and the rest of the table is exact.
+        pub(crate) fn best_by_opened(&mut self, minutes: u32) -> HashMap<u64, u32> {
+            let goal = match self.pruning {
+                Pruning::None => Goal::AtLeast(0),
+                _ => {
+                    let best = self.best(minutes);
+                    let opened = self.best_route.iter().fold(0, |mask, v| mask | (1 << v));
+
+                    // The other actor's plan, with the first one's valves already open.
+                    self.reset();
+                    self.visit((self.network.start, minutes, opened), 0, Goal::Best);
+                    let partner = self.best;
+
+                    // Neither plan can be worth more than the best one alone.
+                    debug_assert!(partner <= best);
+                    Goal::AtLeast(partner)
+                }
+            };
+
+            self.reset();
+            self.visit((self.network.start, minutes, 0), 0, goal);
+            std::mem::take(&mut self.best_by_opened)
+        }
+
+        // The most pressure that could still be released from this state.
+        fn upper_bound(&self, (position, time_left, opened): State) -> u32 {
+            let closed = (0..self.network.flowing).filter(|v| opened & (1 << v) == 0);
+
+            match self.pruning {
+                Pruning::None => u32::MAX,
+                Pruning::Reachable => closed
+                    .map(|v| {
+                        let cost = self.network.distances[position][v].saturating_add(1);
+                        self.network.flow_rates[v] * time_left.saturating_sub(cost)
+                    })
+                    .sum(),
+                Pruning::Sorted => {
+                    let mut flow_rates = closed
+                        .map(|v| self.network.flow_rates[v])
+                        .collect::<Vec<_>>();
+                    flow_rates.sort_unstable_by(|a, b| b.cmp(a));
+
+                    let step = self.shortest_hop + 1;
+                    flow_rates
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, flow_rate)| {
+                            flow_rate * time_left.saturating_sub(step * (i as u32 + 1))
+                        })
+                        .sum()
+                }
+            }
+        }
+
+        fn visit(&mut self, state: State, released: u32, goal: Goal) {
+            match self.memo.get(&state) {
+                Some(previous) if *previous >= released => {
+                    self.stats.dominated += 1;
+                    return;
+                }
+                _ => {
+                    self.memo.insert(state, released);
+                }
+            }
+
+            let (position, time_left, opened) = state;
+
+            if released > self.best {
+                self.best = released;
+                self.best_route = self.route.clone();
+            }
+
+            let entry = self.best_by_opened.entry(opened).or_insert(0);
+
+            if released >= *entry {
+                *entry = released;
+                self.routes_by_opened.insert(opened, self.route.clone());
+            }
+
+            let reachable = released.saturating_add(self.upper_bound(state));
+            let hopeless = match goal {
+                Goal::Best => reachable <= self.best,
+                Goal::AtLeast(target) => reachable < target,
+            };
+
+            if hopeless {
+                self.stats.pruned += 1;
+                return;
+            }
+
+            self.stats.explored += 1;
+
+            for next in 0..self.network.flowing {
+                let flow_rate = self.network.flow_rates[next];
+
+                if opened & (1 << next) != 0 {
+                    continue;
+                }
+
+                // Walking there and then a minute to open it. One-way tunnels
+                // can leave valves unreachable from here.
+                let cost = self.network.distances[position][next].saturating_add(1);
+
+                if cost >= time_left {
+                    continue;
+                }
+
+                let remaining = time_left - cost;
+
+                self.route.push(next);
+                self.visit(
+                    (next, remaining, opened | (1 << next)),
+                    released + remaining * flow_rate,
+                    goal,
+                );
+                self.route.pop();
+            }
+        }
+    }
+
+    /// The highest total of two plans that don't open any of the same valves,
+    /// along with the two sets of valves.
+    pub(crate) fn best_disjoint_pair(best_by_opened: &HashMap<u64, u32>) -> (u32, u64, u64) {
+        let mut plans = best_by_opened.iter().collect::<Vec<_>>();
+        plans.sort_unstable_by(|a, b| b.1.cmp(a.1));
+
+        let mut best = 0;
+        let mut pair = (0, 0);
+
+        for (index, (mine, my_pressure)) in plans.iter().enumerate() {
+            // The partner can be no better than this plan from here on.
+            if *my_pressure * 2 <= best {
+                break;
+            }
+
+            for (theirs, their_pressure) in plans[index..].iter() {
+                if *my_pressure + *their_pressure <= best {
+                    break;
+                }
+
+                if *mine & *theirs == 0 {
+                    best = *my_pressure + *their_pressure;
+                    pair = (**mine, **theirs);
+                    break;
+                }
+            }
+        }
+
+        (best, pair.0, pair.1)
+    }
+}
+
+mod schedule {
+    use crate::Network;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub(crate) enum Action {
+        /// Walks through a tunnel to the valve (an index into the full set).
+        Move(usize),
+        /// Opens the valve it's standing at (an index into the kept set).
+        Open(usize),
+        Idle,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub(crate) struct Minute {
+        pub(crate) minute: u32,
+        /// The valves open at the start of the minute.
+        pub(crate) open: Vec<usize>,
+        pub(crate) releasing: u32,
+        /// The pressure released so far, including this minute.
+        pub(crate) released: u32,
+        /// What each actor does, in the same order as the routes.
+        pub(crate) actions: Vec<Action>,
+    }
+
+    /// Walks each actor's route through the tunnels minute by minute.
+    pub(crate) fn build(network: &Network, routes: &[&[usize]], minutes: u32) -> Vec<Minute> {
+        let timelines = routes
+            .iter()
+            .map(|route| {
+                let mut actions = vec![];
+                let mut position = network.start;
+
+                for valve in route.iter() {
+                    let steps = network.walk(position, *valve);
+                    actions.extend(steps.into_iter().map(Action::Move));
+                    actions.push(Action::Open(*valve));
+                    position = *valve;
+                }
+
+                actions
+            })
+            .collect::<Vec<_>>();
+
+        let mut open: Vec<usize> = vec![];
+        let mut released = 0;
+
+        (0..minutes as usize)
+            .map(|index| {
+                let releasing = open.iter().map(|v| network.flow_rates[*v]).sum();
+                released += releasing;
+
+                let actions = timelines
+                    .iter()
+                    .map(|timeline| *timeline.get(index).unwrap_or(&Action::Idle))
+                    .collect::<Vec<_>>();
+
+                let minute = Minute {
+                    minute: index as u32 + 1,
+                    open: open.clone(),
+                    releasing,
+                    released,
+                    actions: actions.clone(),
+                };
+
+                for action in actions {
+                    if let Action::Open(valve) = action {
+                        open.push(valve);
+                        open.sort_by(|a, b| network.ids[*a].cmp(&network.ids[*b]));
+                    }
+                }
+
+                minute
+            })
+            .collect()
+    }
+
+    fn list(ids: &[&str]) -> String {
+        match ids {
+            [] => String::new(),
+            [only] => only.to_string(),
+            [first, second] => format!("{first} and {second}"),
+            [rest @ .., last] => format!("{}, and {last}", rest.join(", ")),
+        }
+    }
+
+    /// Writes the schedule the way the puzzle's walkthrough does, plus a
+    /// running total. Actors are named in the same order as the routes.
+    pub(crate) fn to_text(network: &Network, schedule: &[Minute], actors: &[&str]) -> String {
+        let mut text = String::new();
+
+        for minute in schedule {
+            text += &format!("== Minute {} ==\n", minute.minute);
+
+            let open = minute
+                .open
+                .iter()
+                .map(|v| network.ids[*v].as_str())
+                .collect::<Vec<_>>();
+
+            match open.len() {
+                0 => text += "No valves are open.\n",
+                1 => {
+                    text += &format!(
+                        "Valve {} is open, releasing {} pressure.\n",
+                        open[0], minute.releasing
+                    )
+                }
+                _ => {
+                    text += &format!(
+                        "Valves {} are open, releasing {} pressure.\n",
+                        list(&open),
+                        minute.releasing
+                    )
+                }
+            }
+
+            for (actor, action) in actors.iter().zip(minute.actions.iter()) {
+                // "You move" but "The elephant moves".
+                let suffix = if *actor == "You" { "" } else { "s" };
+
+                match action {
+                    Action::Move(valve) => {
+                        text += &format!(
+                            "{actor} move{suffix} to valve {}.\n",
+                            network.all_ids[*valve]
+                        )
+                    }
+                    Action::Open(valve) => {
+                        text += &format!("{actor} open{suffix} valve {}.\n", network.ids[*valve])
+                    }
+                    Action::Idle => {}
+                }
+            }
+
+            text += &format!("Released so far: {}.\n\n", minute.released);
+        }
+
+        text
+    }
+
+    pub(crate) fn to_json(network: &Network, schedule: &[Minute], actors: &[&str]) -> String {
+        let minutes = schedule
+            .iter()
+            .map(|minute| {
+                let open = minute
+                    .open
+                    .iter()
+                    .map(|v| format!("\"{}\"", network.ids[*v]))
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                let actions = actors
+                    .iter()
+                    .zip(minute.actions.iter())
+                    .map(|(actor, action)| {
+                        let (kind, valve) = match action {
+                            Action::Move(valve) => ("move", Some(&network.all_ids[*valve])),
+                            Action::Open(valve) => ("open", Some(&network.ids[*valve])),
+                            Action::Idle => ("idle", None),
+                        };
+                        let valve = valve.map_or("null".to_string(), |v| format!("\"{v}\""));
+
+                        format!("{{\"actor\":\"{actor}\",\"action\":\"{kind}\",\"valve\":{valve}}}")
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                format!(
+                    "{{\"minute\":{},\"open\":[{open}],\"releasing\":{},\"released\":{},\"actions\":[{actions}]}}",
+                    minute.minute, minute.releasing, minute.released
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",\n");
+
+        format!("[\n{minutes}\n]")
+    }
+}
+
+mod dot {
+    use crate::Network;
+    use std::collections::HashSet;
+    use std::path::Path;
+    use std::process::Command;
+
+    // One colour per actor for highlighting routes.
+    const COLORS: [&str; 2] = ["royalblue", "darkorange"];
+
+    // The path each actor walks, as indexes into the full set of valves.
+    fn walked(network: &Network, routes: &[&[usize]]) -> Vec<Vec<usize>> {
+        routes
+            .iter()
+            .map(|route| {
+                let mut position = network.start;
+                let mut path = vec![network.indexes[position]];
+
+                for valve in route.iter() {
+                    path.extend(network.walk(position, *valve));
+                    position = *valve;
+                }
+
+                path
+            })
+            .collect()
+    }
+
+    fn node(id: &str, flow_rate: u32, color: Option<&str>) -> String {
+        let style = match (flow_rate, color) {
+            (_, Some(color)) => format!(", style=filled, fillcolor={color}, fontcolor=white"),
+            (0, None) => ", shape=point, width=0.15".to_string(),
+            _ => ", style=filled, fillcolor=lightgrey".to_string(),
+        };
+
+        format!("    {id} [label=\"{id}\\n{flow_rate}\"{style}];\n")
+    }
+
+    /// Every valve and tunnel. Valves with no flow are drawn as points, and
+    /// the tunnels each actor walks along are coloured in.
+    pub(crate) fn tunnels(network: &Network, routes: &[&[usize]]) -> String {
+        let walked = walked(network, routes);
+        let mut text = "graph tunnels {\n    node [shape=circle, fontsize=10];\n".to_string();
+
+        for (index, id) in network.all_ids.iter().enumerate() {
+            let flow_rate = network
+                .indexes
+                .iter()
+                .position(|v| *v == index)
+                .map_or(0, |v| network.flow_rates[v]);
+
+            // Only the valves an actor opens are highlighted.
+            let color = routes
+                .iter()
+                .position(|route| route.iter().any(|v| network.indexes[*v] == index))
+                .map(|actor| COLORS[actor % COLORS.len()]);
+
+            text += &node(id, flow_rate, color);
+        }
+
+        let mut drawn = HashSet::new();
+
+        for (actor, path) in walked.iter().enumerate() {
+            for pair in path.windows(2) {
+                let key = (pair[0].min(pair[1]), pair[0].max(pair[1]));
+
+                if drawn.insert(key) {
+                    text += &format!(
+                        "    {} -- {} [color={}, penwidth=3];\n",
+                        network.all_ids[key.0],
+                        network.all_ids[key.1],
+                        COLORS[actor % COLORS.len()]
+                    );
+                }
+            }
+        }
+
+        for (from, connections) in network.tunnels.iter().enumerate() {
+            for to in connections.iter() {
+                let key = (from.min(*to), from.max(*to));
+
+                if drawn.insert(key) {
+                    text += &format!(
+                        "    {} -- {};\n",
+                        network.all_ids[key.0], network.all_ids[key.1]
+                    );
+                }
+            }
+        }
+
+        text + "}\n"
+    }
+
+    /// The start and the valves worth opening, joined by their travel times,
+    /// with the hops of each actor's route coloured in.
+    pub(crate) fn network(network: &Network, routes: &[&[usize]]) -> String {
+        let mut text = "graph network {\n    node [shape=circle, fontsize=10];\n".to_string();
+
+        for (index, id) in network.ids.iter().enumerate() {
+            let color = routes
+                .iter()
+                .position(|route| route.contains(&index))
+                .map(|actor| COLORS[actor % COLORS.len()]);
+
+            text += &node(id, network.flow_rates[index], color);
+        }
+
+        let mut hops = vec![];
+
+        for (actor, route) in routes.iter().enumerate() {
+            let mut position = network.start;
+
+            for valve in route.iter() {
+                hops.push(((position.min(*valve), position.max(*valve)), actor));
+                position = *valve;
+            }
+        }
+
+        for from in 0..network.ids.len() {
+            for to in from + 1..network.ids.len() {
+                let style = match hops.iter().find(|(hop, _)| *hop == (from, to)) {
+                    Some((_, actor)) => {
+                        format!(", color={}, penwidth=3", COLORS[actor % COLORS.len()])
+                    }
+                    None => ", color=grey".to_string(),
+                };
+
+                text += &format!(
+                    "    {} -- {} [label={}{style}];\n",
+                    network.ids[from], network.ids[to], network.distances[from][to]
+                );
+            }
+        }
+
+        text + "}\n"
+    }
+
+    /// Renders a DOT file to an SVG beside it, if Graphviz is installed.
+    pub(crate) fn render(path: &Path) -> Option<std::path::PathBuf> {
+        let output = path.with_extension("svg");
+
+        let status = Command::new("dot")
+            .arg("-Tsvg")
+            .arg(path)
+            .arg("-o")
+            .arg(&output)
+            .status()
+            .ok()?;
+
+        status.success().then_some(output)
+    }
+}
+
+struct Solution {
+    pressure: u32,
+    // The valves each actor opens, in order.
+    routes: Vec<Vec<usize>>,
+    stats: Stats,
+}
+
+fn part_one(network: &Network, pruning: Pruning) -> Solution {
+    let mut engine = Engine::new(network, pruning);
+    let pressure = engine.best(30);
+
+    Solution {
+        pressure,
+        routes: vec![engine.best_route().to_vec()],
+        stats: engine.stats(),
+    }
+}
+
+fn part_two(network: &Network, pruning: Pruning) -> Solution {
+    // Neither of us gains anything by opening a valve the other has, so the
+    // best plan is the best pair of disjoint sets.
+    let mut engine = Engine::new(network, pruning);
+    let best_by_opened = engine.best_by_opened(26);
+    let (pressure, mine, theirs) = best_disjoint_pair(&best_by_opened);
+
+    Solution {
+        pressure,
+        routes: vec![
+            engine.route_for(mine).to_vec(),
+            engine.route_for(theirs).to_vec(),
+        ],
+        stats: engine.stats(),
+    }
 }
 
 fn main() {
     let input = include_str!("input.txt");
-    let data = parse(input).expect("Failed to parse input.").1;
-    let mut arena = Arena::new();
-    arena.reserve(data.len());
-    
-    for valve in data {
-        let node = arena.new_node(valve);
+    let graph = parse(input).unwrap_or_else(|error| panic!("Failed to parse input. {error}"));
+    let network = Network::new(&graph, "AA")
+        .unwrap_or_else(|error| panic!("Failed to build the network. {error}"));
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let value = |flag: &str| {
+        args.iter()
+            .position(|a| a == flag)
+            .and_then(|i| args.get(i + 1))
+            .map(|s| s.as_str())
+    };
+
+    let pruning = value("--pruning")
+        .map(|name| Pruning::from_name(name).expect("Unknown pruning strategy."))
+        .unwrap_or(Pruning::Sorted);
+    let show_stats = args.iter().any(|a| a == "--stats");
+    let export_dot = args.iter().any(|a| a == "--dot");
+    // Either "--trace" or "--trace json".
+    let trace = args
+        .iter()
+        .any(|a| a == "--trace")
+        .then(|| value("--trace").unwrap_or("text"));
+
+    let parts = [
+        ("One", part_one(&network, pruning), 30, vec!["You"]),
+        (
+            "Two",
+            part_two(&network, pruning),
+            26,
+            vec!["You", "The elephant"],
+        ),
+    ];
+
+    for (name, solution, minutes, actors) in parts {
+        println!("Part {name}: {}", solution.pressure);
+
+        if show_stats {
+            let stats = solution.stats;
+            println!(
+                "Explored {} states ({} dominated, {} pruned)",
+                stats.explored, stats.dominated, stats.pruned
+            );
+        }
+
+        let routes = solution
+            .routes
+            .iter()
+            .map(|r| r.as_slice())
+            .collect::<Vec<_>>();
+
+        if let Some(format) = trace {
+            let plan = schedule::build(&network, &routes, minutes);
+
+            match format {
+                "json" => println!("{}", schedule::to_json(&network, &plan, &actors)),
+                _ => print!("{}", schedule::to_text(&network, &plan, &actors)),
+            }
+        }
+
+        if export_dot {
+            let Some(desktop) = dirs::desktop_dir() else {
+                continue;
+            };
+
+            let graphs = [
+                ("Tunnels", dot::tunnels(&network, &routes)),
+                ("Network", dot::network(&network, &routes)),
+            ];
+
+            for (kind, graph) in graphs {
+                let mut path = desktop.clone();
+                path.push(format!("Day 16 Part {name} {kind}.dot"));
+                std::fs::write(&path, graph).expect("Failed to save graph.");
+                println!("Saved {} graph to {}", kind.to_lowercase(), path.display());
+
+                if let Some(svg) = dot::render(&path) {
+                    println!("Rendered it to {}", svg.display());
+                }
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{parse, Valve};
+    use crate::{
+        dot, parse, parse_line, part_one, part_two, schedule, Network, NetworkError, ParseError,
+        ParseErrorKind, Pruning, Valve,
+    };
 
     #[test]
     fn parsing_works() {
         let input = "Valve DS has flow rate=21; tunnel leads to valve PB\nValve QQ has flow rate=0; tunnels lead to valves FS, ID";
-        let parsed = parse(input);
-        
-        assert_eq!(parsed, Ok(
-            (
-                "",
-                vec![
-                    Valve { 
-                        id: "DS", 
-                        flow_rate: 21, 
-                        connection_ids: vec!["PB"], 
-                    },
-                    Valve { 
-                        id: "QQ", 
-                        flow_rate: 0, 
-                        connection_ids: vec!["FS", "ID"], 
-                    },
-                ],
-            )
+        let lines = input.lines().map(parse_line).collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            vec![
+                Ok(("", ("DS", 21, vec!["PB"]))),
+                Ok(("", ("QQ", 0, vec!["FS", "ID"]))),
+            ]
+        );
+
+        let input = "Valve DS has flow rate=21; tunnel leads to valve LONG\r\nValve LONG has flow rate=0; tunnels lead to valves DS, DS\r\n\n";
+        let graph = parse(input).unwrap();
+
+        assert_eq!(
+            graph.valves,
+            vec![
+                Valve {
+                    id: "DS".to_string(),
+                    flow_rate: 21,
+                    connections: vec![1],
+                },
+                Valve {
+                    id: "LONG".to_string(),
+                    flow_rate: 0,
+                    connections: vec![0, 0],
+                },
+            ]
+        );
+        assert_eq!(graph.index_of("LONG"), Some(1));
+    }
+
+    #[test]
+    fn parse_errors_are_located() {
+        let error = |input: &str| parse(input).unwrap_err();
+
+        let valid = "Valve AA has flow rate=0; tunnel leads to valve BB\n";
+        let defined =
+            |rest: &str| format!("{valid}Valve BB has flow rate=1; tunnel leads to valve AA{rest}");
+
+        assert_eq!(
+            error(&format!(
+                "{valid}Valve BB has flow rate=x; tunnel leads to valve AA"
+            )),
+            ParseError {
+                line: 2,
+                column: 24,
+                kind: ParseErrorKind::Syntax
+            }
+        );
+        assert_eq!(
+            error(&defined(" and more")),
+            ParseError {
+                line: 2,
+                column: 51,
+                kind: ParseErrorKind::TrailingInput
+            }
+        );
+        assert_eq!(
+            error(&defined(", CC")),
+            ParseError {
+                line: 2,
+                column: 53,
+                kind: ParseErrorKind::UndefinedValve("CC".to_string())
+            }
+        );
+        assert_eq!(
+            error(&defined(
+                "\nValve AA has flow rate=3; tunnel leads to valve BB"
+            )),
+            ParseError {
+                line: 3,
+                column: 7,
+                kind: ParseErrorKind::DuplicateValve("AA".to_string())
+            }
+        );
+        assert_eq!(
+            error(&defined(", CC")).to_string(),
+            "Line 2, column 53: valve CC is never defined"
+        );
+    }
+
+    #[test]
+    fn compression_works() {
+        let data = parse(include_str!("test.txt")).unwrap();
+        let network = Network::new(&data, "AA").unwrap();
+
+        assert_eq!(network.ids, ["BB", "CC", "DD", "EE", "HH", "JJ", "AA"]);
+        assert_eq!(network.distances[network.start], [1, 2, 1, 2, 5, 2, 0]);
+    }
+
+    #[test]
+    fn unreachable_valves_are_dropped() {
+        // ZZ and YY are their own little island, well away from AA.
+        let input = "Valve AA has flow rate=0; tunnel leads to valve BB
+Valve BB has flow rate=5; tunnel leads to valve AA
+Valve ZZ has flow rate=40; tunnel leads to valve YY
+Valve YY has flow rate=0; tunnel leads to valve ZZ";
+        let network = Network::new(&parse(input).unwrap(), "AA").unwrap();
+
+        assert_eq!(network.ids, ["BB", "AA"]);
+        assert_eq!(part_one(&network, Pruning::None).pressure, 5 * 28);
+
+        for pruning in [Pruning::Reachable, Pruning::Sorted] {
+            assert_eq!(part_one(&network, pruning).pressure, 5 * 28);
+            assert_eq!(part_two(&network, pruning).pressure, 5 * 24);
+        }
+    }
+
+    #[test]
+    fn network_errors() {
+        let input = "Valve AA has flow rate=0; tunnel leads to valve AA";
+        assert_eq!(
+            Network::new(&parse(input).unwrap(), "XX").err(),
+            Some(NetworkError::UnknownStart("XX".to_string()))
+        );
+
+        // A ring of valves with names AA, AB, ... and a flow rate on each.
+        let ring = |count: usize| {
+            let id = |i: usize| {
+                let i = i % count;
+                format!(
+                    "{}{}",
+                    (b'A' + (i / 26) as u8) as char,
+                    (b'A' + (i % 26) as u8) as char
+                )
+            };
+
+            (0..count)
+                .map(|i| {
+                    format!(
+                        "Valve {} has flow rate=1; tunnels lead to valves {}, {}\n",
+                        id(i),
+                        id(i + count - 1),
+                        id(i + 1)
+                    )
+                })
+                .collect::<String>()
+        };
+
+        assert!(Network::new(&parse(&ring(64)).unwrap(), "AA").is_ok());
+        assert_eq!(
+            Network::new(&parse(&ring(65)).unwrap(), "AA").err(),
+            Some(NetworkError::TooManyValves(65))
+        );
+    }
+
+    #[test]
+    fn part_one_works() {
+        let data = parse(include_str!("test.txt")).unwrap();
+        let network = Network::new(&data, "AA").unwrap();
+        assert_eq!(part_one(&network, Pruning::Sorted).pressure, 1651);
+    }
+
+    #[test]
+    fn part_two_works() {
+        let data = parse(include_str!("test.txt")).unwrap();
+        let network = Network::new(&data, "AA").unwrap();
+        assert_eq!(part_two(&network, Pruning::Sorted).pressure, 1707);
+    }
+
+    #[test]
+    fn pruning_strategies_agree() {
+        let data = parse(include_str!("test.txt")).unwrap();
+        let network = Network::new(&data, "AA").unwrap();
+
+        let none = part_one(&network, Pruning::None);
+        let reachable = part_one(&network, Pruning::Reachable);
+        let sorted = part_one(&network, Pruning::Sorted);
+
+        assert_eq!(none.pressure, 1651);
+        assert_eq!(reachable.pressure, 1651);
+        assert_eq!(sorted.pressure, 1651);
+        assert_eq!(none.stats.pruned, 0);
+        assert!(reachable.stats.explored < none.stats.explored);
+        assert!(sorted.stats.explored < none.stats.explored);
+
+        let none = part_two(&network, Pruning::None);
+        assert_eq!(none.pressure, 1707);
+        assert_eq!(none.stats.pruned, 0);
+
+        for pruning in [Pruning::Reachable, Pruning::Sorted] {
+            let pruned = part_two(&network, pruning);
+            assert_eq!(pruned.pressure, 1707);
+            assert!(pruned.stats.pruned > 0);
+        }
+    }
+
+    #[test]
+    fn schedule_matches_walkthrough() {
+        let data = parse(include_str!("test.txt")).unwrap();
+        let network = Network::new(&data, "AA").unwrap();
+
+        let solution = part_one(&network, Pruning::Sorted);
+        let routes = solution
+            .routes
+            .iter()
+            .map(|r| r.as_slice())
+            .collect::<Vec<_>>();
+        let plan = schedule::build(&network, &routes, 30);
+        assert_eq!(plan.last().unwrap().released, 1651);
+
+        let text = schedule::to_text(&network, &plan, &["You"]);
+        assert!(text.starts_with(
+            "== Minute 1 ==\nNo valves are open.\nYou move to valve DD.\nReleased so far: 0.\n\n\
+             == Minute 2 ==\nNo valves are open.\nYou open valve DD.\nReleased so far: 0.\n\n\
+             == Minute 3 ==\nValve DD is open, releasing 20 pressure.\nYou move to valve CC.\n"
         ));
+        assert!(text.contains("Valves BB, DD, and JJ are open, releasing 54 pressure."));
+
+        let solution = part_two(&network, Pruning::Sorted);
+        let routes = solution
+            .routes
+            .iter()
+            .map(|r| r.as_slice())
+            .collect::<Vec<_>>();
+        let plan = schedule::build(&network, &routes, 26);
+        assert_eq!(plan.last().unwrap().released, 1707);
+
+        let json = schedule::to_json(&network, &plan, &["You", "The elephant"]);
+        assert!(json.starts_with("[\n{\"minute\":1,\"open\":[],\"releasing\":0,\"released\":0,"));
+        assert!(json.contains("\"releasing\":81,\"released\":1707,"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn dot_export_works() {
+        let data = parse(include_str!("test.txt")).unwrap();
+        let network = Network::new(&data, "AA").unwrap();
+        let solution = part_two(&network, Pruning::Sorted);
+        let routes = solution
+            .routes
+            .iter()
+            .map(|r| r.as_slice())
+            .collect::<Vec<_>>();
+
+        let tunnels = dot::tunnels(&network, &routes);
+        assert!(tunnels.starts_with("graph tunnels {"));
+        assert!(tunnels.contains("    FF [label=\"FF\\n0\", shape=point, width=0.15];"));
+        // Every tunnel is drawn once, whichever way it was listed.
+        assert_eq!(tunnels.matches(" -- ").count(), 10);
+        assert_eq!(tunnels.matches("penwidth=3").count(), 9);
+
+        let compressed = dot::network(&network, &routes);
+        assert_eq!(compressed.matches(" -- ").count(), 21);
+        assert!(compressed.contains("    HH -- AA [label=5, color=grey];"));
+        assert_eq!(compressed.matches("penwidth=3").count(), 6);
+    }
+}
@@ -1,145 +1,490 @@
 #![allow(non_snake_case)]
 
-use core::cmp::Ordering;
+use render::Bounds;
+use rope::{Motion, Rope};
 
 fn main() {
     let input = include_str!("input.txt");
+    let args = std::env::args().collect::<Vec<_>>();
+
     let part_one = visited_tail_positions(input, 2);
     let part_two = visited_tail_positions(input, 10);
     println!("Unique Positions Visited by Tail:");
     println!("  Part One (2 knots): {part_one}");
     println!("  Part Two (10 knots): {part_two}");
+
+    // `--show [file]` prints the rope after every motion in a file, or the
+    // puzzle input. The pictures below are drawn from the same motions.
+    let show = args.iter().position(|a| a == "--show");
+    let file = show
+        .and_then(|index| args.get(index + 1))
+        .filter(|a| !a.starts_with("--"))
+        .map(|path| std::fs::read_to_string(path).expect("Failed to read motion file."));
+    let motions = rope::parse(file.as_deref().unwrap_or(input));
+
+    // `--knots <n>` sets the length of the rope that is drawn.
+    let knots = args
+        .iter()
+        .position(|a| a == "--knots")
+        .map(|index| {
+            args.get(index + 1)
+                .and_then(|a| a.parse::<usize>().ok())
+                .expect("Expected a knot count after --knots.")
+        })
+        .unwrap_or(10);
+
+    let finished = replay(&motions, knots, |_, _| {});
+    let bounds = Bounds::fit(&finished);
+
+    if show.is_some() {
+        replay(&motions, knots, |motion, rope| {
+            println!();
+            println!("== {motion} ==");
+            println!();
+            print!("{}", render::text(rope, &bounds));
+        });
+    }
+
+    if !args.iter().any(|a| a == "--noimage") {
+        if let Some(mut path) = dirs::desktop_dir() {
+            path.push("Day 9 Rope.png");
+            graphic::picture(&finished, &bounds)
+                .save(path.clone())
+                .expect("Failed to save image.");
+            println!("Saved rope image to {}", path.to_str().unwrap());
+
+            // `--gif` also animates the whole run, a frame per motion.
+            if args.iter().any(|a| a == "--gif") {
+                path.set_extension("gif");
+                let mut animation =
+                    graphic::Animation::create(&path).expect("Failed to create animation.");
+                replay(&motions, knots, |_, rope| {
+                    animation
+                        .push(graphic::picture(rope, &bounds))
+                        .expect("Failed to save animation.")
+                });
+                println!("Saved rope animation to {}", path.to_str().unwrap());
+            }
+        }
+    }
 }
 
-fn visited_tail_positions(input: &str, knots: usize) -> usize {
-    if knots == 0 {
-        return 0;
+mod rope {
+    use std::collections::HashSet;
+    use std::fmt::{Display, Formatter};
+    use std::str::FromStr;
+
+    /// `(x, y)`, with up being positive y.
+    pub(crate) type Position = (isize, isize);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(crate) enum Direction {
+        Up,
+        Down,
+        Left,
+        Right,
+        UpLeft,
+        UpRight,
+        DownLeft,
+        DownRight,
+    }
+
+    impl Direction {
+        pub(crate) fn delta(&self) -> Position {
+            match self {
+                Direction::Up => (0, 1),
+                Direction::Down => (0, -1),
+                Direction::Left => (-1, 0),
+                Direction::Right => (1, 0),
+                Direction::UpLeft => (-1, 1),
+                Direction::UpRight => (1, 1),
+                Direction::DownLeft => (-1, -1),
+                Direction::DownRight => (1, -1),
+            }
+        }
     }
 
-    let mut knot_positions = vec![];
+    impl FromStr for Direction {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "U" => Ok(Direction::Up),
+                "D" => Ok(Direction::Down),
+                "L" => Ok(Direction::Left),
+                "R" => Ok(Direction::Right),
+                "UL" | "LU" => Ok(Direction::UpLeft),
+                "UR" | "RU" => Ok(Direction::UpRight),
+                "DL" | "LD" => Ok(Direction::DownLeft),
+                "DR" | "RD" => Ok(Direction::DownRight),
+                _ => Err(format!("Invalid direction '{s}'.")),
+            }
+        }
+    }
 
-    for _ in 0..knots {
-        knot_positions.push((0, 0));
+    impl Display for Direction {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            let name = match self {
+                Direction::Up => "U",
+                Direction::Down => "D",
+                Direction::Left => "L",
+                Direction::Right => "R",
+                Direction::UpLeft => "UL",
+                Direction::UpRight => "UR",
+                Direction::DownLeft => "DL",
+                Direction::DownRight => "DR",
+            };
+            write!(f, "{name}")
+        }
     }
 
-    let mut visited_tail_positions: Vec<(isize, isize)> = vec![(0, 0)];
-    let knot_offsets: Vec<(isize, isize)> = (-1..=1)
-        .flat_map(|i| (-1..=1).map(move |j| (i, j)))
-        .collect();
+    /// A line of the input: which way the head goes, and how many steps.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(crate) struct Motion {
+        pub(crate) direction: Direction,
+        pub(crate) count: usize,
+    }
 
-    for line in input.lines() {
-        let trimmed = line.trim();
-        let direction_char = trimmed.chars().next().unwrap();
-        let count_str = &trimmed[2..];
-        let count = count_str.parse::<usize>().unwrap();
+    impl Display for Motion {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{} {}", self.direction, self.count)
+        }
+    }
+
+    pub(crate) fn parse(input: &str) -> Vec<Motion> {
+        input
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(|line| {
+                let (direction, count) = line
+                    .split_once(' ')
+                    .expect("Expected a direction and a count.");
+
+                Motion {
+                    direction: direction.parse().unwrap_or_else(|error| panic!("{error}")),
+                    count: count.parse().expect("Unable to parse the step count."),
+                }
+            })
+            .collect()
+    }
+
+    /// A rope of any length, remembering every cell each of its knots has been
+    /// in. The first knot is the head.
+    pub(crate) struct Rope {
+        knots: Vec<Position>,
+        visited: Vec<HashSet<Position>>,
+    }
+
+    impl Rope {
+        pub(crate) fn new(knots: usize) -> Self {
+            Self {
+                knots: vec![(0, 0); knots],
+                visited: vec![HashSet::from([(0, 0)]); knots],
+            }
+        }
 
-        'outer: for _ in 0..count {
-            match direction_char {
-                'U' => knot_positions[0].1 += 1,
-                'D' => knot_positions[0].1 -= 1,
-                'L' => knot_positions[0].0 -= 1,
-                'R' => knot_positions[0].0 += 1,
-                _ => panic!("Invalid direction."),
+        pub(crate) fn knots(&self) -> &[Position] {
+            &self.knots
+        }
+
+        /// Every cell the given knot has been in, including the start.
+        pub(crate) fn visited(&self, knot: usize) -> &HashSet<Position> {
+            &self.visited[knot]
+        }
+
+        /// Moves the head one step and lets the rest of the rope follow.
+        pub(crate) fn step(&mut self, direction: Direction) {
+            let Some(head) = self.knots.first_mut() else {
+                return;
             };
 
-            for knot_index in 1..knots {
-                // On the first loop, this is the position of the head.
-                let previous_knot = knot_positions[knot_index - 1];
-                let current_knot = &mut knot_positions[knot_index];
-
-                for knot_offset in &knot_offsets {
-                    let position = (
-                        previous_knot.0 + knot_offset.0,
-                        previous_knot.1 + knot_offset.1,
-                    );
-
-                    if position.0 == current_knot.0 && position.1 == current_knot.1 {
-                        // Knot doesn't need to be moved.
-                        // Further, all following knots don't need to be moved.
-                        continue 'outer;
-                    }
+            let delta = direction.delta();
+            head.0 += delta.0;
+            head.1 += delta.1;
+            self.visited[0].insert(*head);
+
+            for index in 1..self.knots.len() {
+                let leader = self.knots[index - 1];
+                let knot = &mut self.knots[index];
+                let gap = (leader.0 - knot.0, leader.1 - knot.1);
+
+                // Still touching, so neither this knot nor any after it moves.
+                if gap.0.abs() <= 1 && gap.1.abs() <= 1 {
+                    break;
                 }
 
-                let xy_matches = (
-                    current_knot.0 == previous_knot.0, // Row
-                    current_knot.1 == previous_knot.1, // Column
-                );
-
-                match xy_matches {
-                    (false, true) => match current_knot.0.cmp(&previous_knot.0) {
-                        Ordering::Greater => current_knot.0 -= 1,
-                        Ordering::Less => current_knot.0 += 1,
-                        _ => panic!(),
-                    },
-                    (true, false) => match current_knot.1.cmp(&previous_knot.1) {
-                        Ordering::Greater => current_knot.1 -= 1,
-                        Ordering::Less => current_knot.1 += 1,
-                        _ => panic!(),
-                    },
-                    (false, false) => {
-                        let delta = (
-                            previous_knot.0 - current_knot.0,
-                            previous_knot.1 - current_knot.1,
-                        );
-
-                        current_knot.0 += delta.0.clamp(-1, 1);
-                        current_knot.1 += delta.1.clamp(-1, 1);
-                    }
-                    _ => panic!(
-                        "The head and tails' positions cannot be equal. This was already checked."
-                    ),
-                };
+                // Straight towards the leader when in line, diagonally otherwise.
+                knot.0 += gap.0.signum();
+                knot.1 += gap.1.signum();
+                self.visited[index].insert(*knot);
             }
+        }
 
-            let tail_position = knot_positions.last().unwrap();
-            if !visited_tail_positions.contains(tail_position) {
-                visited_tail_positions.push(*tail_position);
+        /// Steps through the motions one head step at a time.
+        pub(crate) fn steps<'a>(&'a mut self, motions: &'a [Motion]) -> Steps<'a> {
+            Steps {
+                rope: self,
+                motions,
+                motion: 0,
+                taken: 0,
             }
         }
+    }
+
+    /// Where every knot ended up after a single step of the head.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub(crate) struct Step {
+        /// Which motion the step belongs to.
+        pub(crate) motion: usize,
+        pub(crate) knots: Vec<Position>,
+    }
 
-        // println!("{}:\n{}", trimmed, visualize_positions(knot_positions.clone()));
+    pub(crate) struct Steps<'a> {
+        rope: &'a mut Rope,
+        motions: &'a [Motion],
+        motion: usize,
+        /// Steps already taken in the current motion.
+        taken: usize,
     }
 
-    visited_tail_positions.len()
+    impl Steps<'_> {
+        pub(crate) fn rope(&self) -> &Rope {
+            self.rope
+        }
+    }
+
+    impl Iterator for Steps<'_> {
+        type Item = Step;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            // Skip past finished motions, including any with no steps.
+            while self.taken == self.motions.get(self.motion)?.count {
+                self.motion += 1;
+                self.taken = 0;
+            }
+
+            self.rope.step(self.motions[self.motion].direction);
+            self.taken += 1;
+
+            Some(Step {
+                motion: self.motion,
+                knots: self.rope.knots.clone(),
+            })
+        }
+    }
 }
 
-// fn visualize_positions(positions: Vec<(isize, isize)>) -> String {
-// let mut string = String::new();
-
-// if positions.is_empty() {
-// return string;
-// }
-
-// let min: (isize, isize) = (-10, -10);
-// let max: (isize, isize) = (30, 10);
-
-// for y in (min.1..=max.1).rev() {
-// string.push('\n');
-
-// for x in min.0..=max.0 {
-// if let Some((index, _)) = positions.iter().enumerate().find(|p| p.1.0 == x && p.1.1 == y) {
-// let regular = &format!("{}", 10 - index);
-// string.push_str(if index == 0 { "H" } else { regular });
-// } else if x == 0 && y == 0 {
-// string.push('s');
-// } else if x == 0 && y == -2 {
-// string.push('b');
-// } else if x == 0 {
-// string.push('|');
-// } else if y == 0 {
-// string.push('-');
-// } else {
-// string.push('.');
-// }
-// }
-// }
-
-// string
-// }
+mod render {
+    use crate::rope::{Position, Rope};
+
+    /// The smallest area holding every cell any knot has been in, so a whole
+    /// run can be drawn in one unchanging viewport.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(crate) struct Bounds {
+        pub(crate) min: Position,
+        pub(crate) max: Position,
+    }
+
+    impl Bounds {
+        /// Fits a rope that has already made all of its moves. The start is
+        /// always included, even for a rope with no knots.
+        pub(crate) fn fit(rope: &Rope) -> Self {
+            let mut bounds = Bounds {
+                min: (0, 0),
+                max: (0, 0),
+            };
+
+            for knot in 0..rope.knots().len() {
+                for &(x, y) in rope.visited(knot) {
+                    bounds.min = (bounds.min.0.min(x), bounds.min.1.min(y));
+                    bounds.max = (bounds.max.0.max(x), bounds.max.1.max(y));
+                }
+            }
+
+            bounds
+        }
+
+        pub(crate) fn width(&self) -> usize {
+            (self.max.0 - self.min.0) as usize + 1
+        }
+
+        pub(crate) fn height(&self) -> usize {
+            (self.max.1 - self.min.1) as usize + 1
+        }
+
+        /// Every cell from the top row down, each row left to right.
+        pub(crate) fn cells(&self) -> impl Iterator<Item = Position> + '_ {
+            (self.min.1..=self.max.1)
+                .rev()
+                .flat_map(|y| (self.min.0..=self.max.0).map(move |x| (x, y)))
+        }
+    }
+
+    /// `H` for the head, then each knot's index. A two-knot rope uses `T` for
+    /// its tail like the puzzle does, and anything past the ninth knot is a
+    /// letter.
+    pub(crate) fn label(index: usize, knots: usize) -> char {
+        match index {
+            0 => 'H',
+            1 if knots == 2 => 'T',
+            1..=9 => char::from_digit(index as u32, 10).unwrap(),
+            10..=35 => char::from_digit(index as u32, 36).unwrap(),
+            _ => '*',
+        }
+    }
+
+    /// The rope in the puzzle's notation, with `#` for cells the tail has
+    /// been in and `s` for the start. Knots nearer the head cover the ones
+    /// behind them.
+    pub(crate) fn text(rope: &Rope, bounds: &Bounds) -> String {
+        let knots = rope.knots();
+        let trail = knots.len().checked_sub(1).map(|tail| rope.visited(tail));
+        let mut string = String::new();
+
+        for (x, y) in bounds.cells() {
+            let cell = if let Some(index) = knots.iter().position(|&k| k == (x, y)) {
+                label(index, knots.len())
+            } else if (x, y) == (0, 0) {
+                's'
+            } else if trail.is_some_and(|t| t.contains(&(x, y))) {
+                '#'
+            } else {
+                '.'
+            };
+            string.push(cell);
+
+            if x == bounds.max.0 {
+                string.push('\n');
+            }
+        }
+
+        string
+    }
+}
+
+mod graphic {
+    use crate::render::Bounds;
+    use crate::rope::{Position, Rope};
+    use image::codecs::gif::{GifEncoder, Repeat};
+    use image::{Delay, DynamicImage, Frame, ImageResult, Rgb, RgbImage};
+    use std::fs::File;
+    use std::path::Path;
+
+    const FRAME_MS: u32 = 40;
+
+    const BACKGROUND: Rgb<u8> = Rgb([20, 20, 28]);
+    const TRAIL: Rgb<u8> = Rgb([70, 110, 170]);
+    const START: Rgb<u8> = Rgb([240, 240, 240]);
+    const HEAD: Rgb<u8> = Rgb([235, 60, 50]);
+    const TAIL: Rgb<u8> = Rgb([250, 210, 60]);
+
+    /// Pixels per cell, shrinking as the run covers more ground so the image
+    /// stays a sensible size.
+    pub(crate) fn scale(bounds: &Bounds) -> u32 {
+        let largest = bounds.width().max(bounds.height()) as u32;
+        (800 / largest).clamp(1, 16)
+    }
+
+    /// Knots fade from red at the head to yellow at the tail, standing in
+    /// for the index numbers of the text render.
+    fn knot_color(index: usize, knots: usize) -> Rgb<u8> {
+        let t = index as f32 / (knots.max(2) - 1) as f32;
+        Rgb([0, 1, 2].map(|c| (HEAD.0[c] as f32 + (TAIL.0[c] as f32 - HEAD.0[c] as f32) * t) as u8))
+    }
+
+    /// A looping GIF, written a frame at a time so a long run never has to
+    /// be held in memory.
+    pub(crate) struct Animation {
+        encoder: GifEncoder<File>,
+    }
+
+    impl Animation {
+        pub(crate) fn create(path: &Path) -> ImageResult<Self> {
+            let mut encoder = GifEncoder::new_with_speed(File::create(path)?, 30);
+            encoder.set_repeat(Repeat::Infinite)?;
+            Ok(Self { encoder })
+        }
+
+        pub(crate) fn push(&mut self, picture: RgbImage) -> ImageResult<()> {
+            let delay = Delay::from_numer_denom_ms(FRAME_MS, 1);
+            let rgba = DynamicImage::ImageRgb8(picture).into_rgba8();
+            self.encoder
+                .encode_frame(Frame::from_parts(rgba, 0, 0, delay))
+        }
+    }
+
+    pub(crate) fn picture(rope: &Rope, bounds: &Bounds) -> RgbImage {
+        let scale = scale(bounds);
+        let mut image = RgbImage::from_pixel(
+            bounds.width() as u32 * scale,
+            bounds.height() as u32 * scale,
+            BACKGROUND,
+        );
+
+        let mut fill = |(x, y): Position, color: Rgb<u8>| {
+            let column = (x - bounds.min.0) as u32 * scale;
+            let row = (bounds.max.1 - y) as u32 * scale;
+
+            for py in row..row + scale {
+                for px in column..column + scale {
+                    image.put_pixel(px, py, color);
+                }
+            }
+        };
+
+        let knots = rope.knots();
+
+        if let Some(tail) = knots.len().checked_sub(1) {
+            for &cell in rope.visited(tail) {
+                fill(cell, TRAIL);
+            }
+        }
+
+        fill((0, 0), START);
+
+        // Back to front, so knots nearer the head are drawn on top.
+        for (index, &knot) in knots.iter().enumerate().rev() {
+            fill(knot, knot_color(index, knots.len()));
+        }
+
+        image
+    }
+}
+
+/// Runs the motions on a fresh rope, handing it over after each motion.
+fn replay(motions: &[Motion], knots: usize, mut after: impl FnMut(&Motion, &Rope)) -> Rope {
+    let mut rope = Rope::new(knots);
+
+    for motion in motions {
+        for _ in 0..motion.count {
+            rope.step(motion.direction);
+        }
+
+        after(motion, &rope);
+    }
+
+    rope
+}
+
+fn visited_tail_positions(input: &str, knots: usize) -> usize {
+    if knots == 0 {
+        return 0;
+    }
+
+    let motions = rope::parse(input);
+    let mut rope = Rope::new(knots);
+
+    let mut steps = rope.steps(&motions);
+    steps.by_ref().for_each(drop);
+
+    steps.rope().visited(knots - 1).len()
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rope::{Direction, Step};
 
     #[test]
     fn test_1() {
@@ -172,4 +517,71 @@ mod tests {
         let expected = 36;
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn diagonal_moves() {
+        let motions = rope::parse("UR 3\nDL 1");
+        assert_eq!(motions[0].direction, Direction::UpRight);
+
+        let mut rope = Rope::new(3);
+        let steps = rope.steps(&motions).collect::<Vec<_>>();
+
+        assert_eq!(steps.len(), 4);
+        assert_eq!(
+            steps[2],
+            Step {
+                motion: 0,
+                knots: vec![(3, 3), (2, 2), (1, 1)],
+            }
+        );
+
+        // Stepping back towards the tail doesn't pull anything.
+        assert_eq!(steps[3].knots, vec![(2, 2), (2, 2), (1, 1)]);
+        assert_eq!(rope.visited(0).len(), 4);
+        assert_eq!(rope.visited(1).len(), 3);
+        assert_eq!(rope.visited(2).len(), 2);
+    }
+
+    #[test]
+    fn iterating_matches_stepping() {
+        let motions = rope::parse("R 5\nU 0\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20");
+        let mut rope = Rope::new(10);
+        let mut steps = rope.steps(&motions);
+
+        let count = steps.by_ref().count();
+        assert_eq!(count, 96);
+        assert_eq!(steps.rope().visited(9).len(), 36);
+        assert!(rope.steps(&motions[..0]).next().is_none());
+    }
+
+    #[test]
+    fn render_fits_the_run() {
+        let motions = rope::parse("R 4\nU 4");
+        let rope = replay(&motions, 2, |_, _| {});
+        let bounds = Bounds::fit(&rope);
+
+        assert_eq!((bounds.width(), bounds.height()), (5, 5));
+        assert_eq!(
+            render::text(&rope, &bounds),
+            "....H\n....T\n....#\n....#\ns###.\n"
+        );
+
+        let motions = rope::parse("R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20");
+        let bounds = Bounds::fit(&replay(&motions, 10, |_, _| {}));
+        assert_eq!(bounds.min, (-11, -5));
+        assert_eq!(bounds.max, (14, 15));
+        assert_eq!(
+            graphic::picture(&Rope::new(0), &bounds).dimensions(),
+            (26 * 16, 21 * 16)
+        );
+    }
+
+    #[test]
+    fn knot_labels() {
+        assert_eq!(render::label(0, 10), 'H');
+        assert_eq!(render::label(1, 2), 'T');
+        assert_eq!(render::label(1, 10), '1');
+        assert_eq!(render::label(10, 20), 'a');
+        assert_eq!(render::label(40, 50), '*');
+    }
 }